@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::{CameraConfig, FrameFormat};
+use crate::recording::ReplaySpeed;
+
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid resolution \"{}\", expected WIDTHxHEIGHT", s);
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+
+        Ok(Resolution {
+            width: width.trim().parse().map_err(|_| invalid())?,
+            height: height.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Parser)]
+#[clap(author, version, about)]
+pub struct Args {
+    #[clap(subcommand)]
+    pub action: Option<Action>,
+
+    /// Run the recognition loop without the window
+    #[clap(long)]
+    pub headless: bool,
+
+    /// Configuration file with the signs and the state graph
+    #[clap(long, value_name = "PATH", default_value = "config.yaml")]
+    pub config: String,
+
+    /// Feed the recorded landmarks of this file instead of the camera
+    #[clap(long, value_name = "PATH")]
+    pub replay: Option<String>,
+
+    /// Replay speed relative to the recorded timestamps
+    #[clap(long, value_name = "FACTOR", default_value_t = 1f32)]
+    pub replay_speed: f32,
+
+    /// Replay one recorded frame per tick, ignoring the timestamps
+    #[clap(long)]
+    pub replay_stepped: bool,
+
+    /// Record the tracked landmarks to this file
+    #[clap(long, value_name = "PATH")]
+    pub record: Option<String>,
+
+    /// Index of the capturing device
+    #[clap(long, value_name = "INDEX")]
+    pub camera: Option<usize>,
+
+    /// Capture resolution, e.g. 1280x720
+    #[clap(long, value_name = "WIDTHxHEIGHT")]
+    pub resolution: Option<Resolution>,
+
+    /// Capture frame format: MJPEG or YUYV
+    #[clap(long, value_name = "FORMAT")]
+    pub format: Option<FrameFormat>,
+
+    /// Target capture frames per second
+    #[clap(long, value_name = "FPS")]
+    pub fps: Option<u32>,
+
+    /// Log the commands instead of executing them
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Append the results of the executed programs to this file
+    #[clap(long, value_name = "PATH", default_value = "commands.log")]
+    pub command_log: String,
+}
+
+#[derive(Subcommand)]
+pub enum Action {
+    /// Report the signs matching the same hand pose and exit
+    Validate,
+}
+
+impl Args {
+    // The camera settings of the config file, overridden by the arguments
+    pub fn camera_config(&self, mut camera: CameraConfig) -> CameraConfig {
+        if let Some(index) = self.camera {
+            camera.index = index;
+        }
+        if let Some(ref resolution) = self.resolution {
+            camera.width = resolution.width;
+            camera.height = resolution.height;
+        }
+        if let Some(format) = self.format {
+            camera.format = format;
+        }
+        if let Some(fps) = self.fps {
+            camera.fps = fps;
+        }
+
+        camera
+    }
+
+    pub fn replay_speed(&self) -> ReplaySpeed {
+        if self.replay_stepped {
+            ReplaySpeed::Stepped
+        } else {
+            ReplaySpeed::Timed(self.replay_speed)
+        }
+    }
+}
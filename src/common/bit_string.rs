@@ -1,23 +1,41 @@
 use serde::{Deserialize, Serialize};
 
+// Holds up to 64 bits on every platform
 #[derive(Serialize, Deserialize)]
 pub struct BitString {
-    bits: usize,
+    bits: u64,
     capacity: usize,
 }
 
 impl BitString {
-    pub fn new(bits: usize, capacity: usize) -> Self {
+    pub const MAX_CAPACITY: usize = u64::BITS as usize;
+
+    pub fn new(bits: u64, capacity: usize) -> Self {
+        assert!(
+            capacity <= Self::MAX_CAPACITY,
+            "BitString capacity too large"
+        );
+
         BitString { bits, capacity }
     }
 
-    pub fn bits(&self) -> usize {
+    pub fn bits(&self) -> u64 {
         self.bits
     }
     pub fn capacity(&self) -> usize {
         self.capacity
     }
 
+    // Bits past the old capacity keep their value
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(
+            capacity <= Self::MAX_CAPACITY,
+            "BitString capacity too large"
+        );
+
+        self.capacity = capacity;
+    }
+
     pub fn get(&self, index: usize) -> bool {
         self.bits & (1 << index) > 0
     }
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::common::feature_config::{FeatureConfig, Hysteresis, Measurements};
+
+// Share of the gap between two poses the thresholds are apart, around its middle
+const HYSTERESIS_SHARE: f32 = 0.2f32;
+
+// Captured in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, EnumIter, EnumString)]
+pub enum CalibrationPose {
+    OpenPalm,
+    Fist,
+    FlatHand,
+    Rotated,
+}
+
+impl CalibrationPose {
+    pub fn instructions(&self) -> &'static str {
+        match self {
+            CalibrationPose::OpenPalm => {
+                "Show the open palm upright, with the fingers stretched and spread apart."
+            }
+            CalibrationPose::Fist => "Close the hand into a fist.",
+            CalibrationPose::FlatHand => {
+                "Show the open palm upright, with the fingers stretched and held together."
+            }
+            CalibrationPose::Rotated => "Show the open palm again, turned sideways.",
+        }
+    }
+}
+
+// The poses measured alike, or the wrong way round, for the named thresholds
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationError(pub &'static str);
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The poses did not tell {} apart, calibrate again.",
+            self.0
+        )
+    }
+}
+
+// Sets the feature thresholds between the measurements of opposite poses
+#[derive(Default)]
+pub struct Calibration {
+    poses: HashMap<CalibrationPose, Measurements>,
+}
+
+impl Calibration {
+    // Capturing the first pose again starts over
+    pub fn add(&mut self, pose: CalibrationPose, measurements: Measurements) {
+        if CalibrationPose::iter().next() == Some(pose) {
+            self.poses.clear();
+        }
+
+        self.poses.insert(pose, measurements);
+    }
+
+    // None once every pose is captured
+    pub fn next_pose(&self) -> Option<CalibrationPose> {
+        CalibrationPose::iter().find(|pose| !self.poses.contains_key(pose))
+    }
+
+    // None until every pose is captured, the thresholds the poses don't tell apart are kept from
+    // the current configuration
+    pub fn feature_config(
+        &self,
+        current: &FeatureConfig,
+    ) -> Option<Result<FeatureConfig, CalibrationError>> {
+        let open = self.poses.get(&CalibrationPose::OpenPalm)?;
+        let fist = self.poses.get(&CalibrationPose::Fist)?;
+        let flat = self.poses.get(&CalibrationPose::FlatHand)?;
+        let rotated = self.poses.get(&CalibrationPose::Rotated)?;
+
+        let config = || {
+            Ok(FeatureConfig {
+                finger_closed: split(
+                    average(&fist.finger_distances),
+                    average(&open.finger_distances),
+                    true,
+                )
+                .ok_or(CalibrationError("closed fingers"))?,
+                hand_rotated: split(rotated.hand_angle, open.hand_angle, true)
+                    .ok_or(CalibrationError("rotated hands"))?,
+                finger_spread: split(average(&open.spreads), average(&flat.spreads), false)
+                    .ok_or(CalibrationError("spread fingers"))?,
+                thumb_closed: split(fist.thumb_distance, open.thumb_distance, true)
+                    .ok_or(CalibrationError("closed thumbs"))?,
+                ..current.clone()
+            })
+        };
+
+        Some(config())
+    }
+}
+
+fn average(values: &[f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len().max(1) as f32
+}
+
+// Thresholds between the value with the feature and the one without, None unless the value with
+// the feature is on the expected side
+fn split(on: f32, off: f32, on_below: bool) -> Option<Hysteresis> {
+    if on == off || (on < off) != on_below {
+        return None;
+    }
+
+    let middle = (on + off) / 2f32;
+    let band = (on - off) * HYSTERESIS_SHARE / 2f32;
+
+    Some(Hysteresis {
+        on: middle + band,
+        off: middle - band,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::calibration::{Calibration, CalibrationError, CalibrationPose};
+    use crate::common::feature_config::{FeatureConfig, Measurements};
+
+    fn pose(hand_angle: f32, finger_distance: f32, spread: f32) -> Measurements {
+        Measurements {
+            hand_angle,
+            finger_distances: [finger_distance; 4],
+            spreads: [spread; 4],
+            thumb_distance: finger_distance,
+            ..Measurements::default()
+        }
+    }
+
+    #[test]
+    fn thresholds_between_poses() {
+        let mut calibration = Calibration::default();
+
+        calibration.add(CalibrationPose::OpenPalm, pose(170f32, 2f32, 20f32));
+        calibration.add(CalibrationPose::Fist, pose(170f32, 0.5f32, 5f32));
+        assert_eq!(calibration.next_pose(), Some(CalibrationPose::FlatHand));
+        assert!(calibration
+            .feature_config(&FeatureConfig::default())
+            .is_none());
+
+        calibration.add(CalibrationPose::FlatHand, pose(170f32, 2f32, 4f32));
+        calibration.add(CalibrationPose::Rotated, pose(110f32, 2f32, 20f32));
+        assert_eq!(calibration.next_pose(), None);
+
+        let config = calibration
+            .feature_config(&FeatureConfig::default())
+            .unwrap()
+            .unwrap();
+        // Closed below 1.1 and open again above 1.4
+        assert!((config.finger_closed.on - 1.1f32).abs() < 1e-4);
+        assert!((config.finger_closed.off - 1.4f32).abs() < 1e-4);
+        assert!(config.finger_spread.on > config.finger_spread.off);
+        assert!(config.hand_rotated.on < config.hand_rotated.off);
+
+        calibration.add(CalibrationPose::OpenPalm, pose(170f32, 2f32, 20f32));
+        assert_eq!(calibration.next_pose(), Some(CalibrationPose::Fist));
+    }
+
+    #[test]
+    fn inverted_poses() {
+        let mut calibration = Calibration::default();
+
+        // The fist measured as open as the palm
+        calibration.add(CalibrationPose::OpenPalm, pose(170f32, 2f32, 20f32));
+        calibration.add(CalibrationPose::Fist, pose(170f32, 2.5f32, 5f32));
+        calibration.add(CalibrationPose::FlatHand, pose(170f32, 2f32, 4f32));
+        calibration.add(CalibrationPose::Rotated, pose(110f32, 2f32, 20f32));
+
+        assert_eq!(
+            calibration
+                .feature_config(&FeatureConfig::default())
+                .unwrap()
+                .err(),
+            Some(CalibrationError("closed fingers"))
+        );
+    }
+}
@@ -26,7 +26,16 @@ impl From<&[Point2F]> for Circle {
 }
 
 impl Circle {
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
     pub fn contains(&self, point: &Point2F) -> bool {
         self.center.distance(point) < self.radius
     }
+
+    // Distance of the point to the center, in radii
+    pub fn relative_distance(&self, point: &Point2F) -> f32 {
+        self.center.distance(point) / self.radius.max(f32::EPSILON)
+    }
 }
@@ -0,0 +1,161 @@
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{Handedness, Point3F, Sign, SignMode};
+
+// Examples kept per sign, the oldest ones go first
+pub const MAX_EXAMPLES: usize = 90;
+
+// The landmarks relative to the wrist, in palm lengths, with left hands mirrored onto right ones
+pub fn normalize(landmarks: &[Point3F], handedness: Handedness) -> Vec<f32> {
+    let wrist = landmarks[0];
+    let base = landmarks[9];
+    let palm_length =
+        ((base.x - wrist.x).powi(2) + (base.y - wrist.y).powi(2) + (base.z - wrist.z).powi(2))
+            .sqrt()
+            .max(f32::EPSILON);
+    let side = match handedness {
+        Handedness::Left => -1f32,
+        Handedness::Right => 1f32,
+    };
+
+    landmarks
+        .iter()
+        .flat_map(|landmark| {
+            [
+                side * (landmark.x - wrist.x) / palm_length,
+                (landmark.y - wrist.y) / palm_length,
+                (landmark.z - wrist.z) / palm_length,
+            ]
+        })
+        .collect()
+}
+
+// k nearest neighbours over the examples of the learned signs
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Classifier {
+    // Nearest examples voting for their sign
+    pub k: usize,
+    // Examples further away don't vote, root mean square per landmark in palm lengths
+    pub max_distance: f32,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Classifier {
+            k: 5,
+            max_distance: 0.3f32,
+        }
+    }
+}
+
+impl Classifier {
+    // The votes of the nearest examples for each sign, weighted by how close they are, from 0 to 1
+    pub fn likelihoods(&self, signs: &[&Sign], landmarks: &[f32]) -> Vec<f32> {
+        let mut neighbours: Vec<(usize, f32)> = signs
+            .iter()
+            .enumerate()
+            .filter(|(_, sign)| sign.mode == SignMode::Learned)
+            .flat_map(|(i, sign)| {
+                sign.examples
+                    .iter()
+                    .map(move |example| (i, distance(example, landmarks)))
+            })
+            .filter(|(_, distance)| *distance < self.max_distance)
+            .collect();
+        neighbours.sort_by_key(|(_, distance)| OrderedFloat(*distance));
+
+        let mut likelihoods = vec![0f32; signs.len()];
+        for (i, distance) in neighbours.into_iter().take(self.k) {
+            likelihoods[i] +=
+                (1f32 - (distance / self.max_distance).powi(2)) / self.k.max(1) as f32;
+        }
+
+        likelihoods
+    }
+}
+
+fn distance(example: &[f32], landmarks: &[f32]) -> f32 {
+    if example.len() != landmarks.len() || example.is_empty() {
+        return f32::MAX;
+    }
+
+    let sum: f32 = example
+        .iter()
+        .zip(landmarks)
+        .map(|(a, b)| (a - b).powi(2))
+        .sum();
+
+    (sum * 3f32 / example.len() as f32).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::classifier::{normalize, Classifier};
+    use crate::common::{Handedness, Point3F, Sign, SignMode};
+
+    // A hand with the fingers bent by the given amount
+    fn hand(bend: f32, x: f32, scale: f32) -> Vec<Point3F> {
+        (0..21)
+            .map(|i| {
+                let finger = (i as f32 - 1f32).max(0f32) / 4f32;
+                let joint = ((i as f32 - 1f32).max(0f32) % 4f32) / 4f32;
+
+                Point3F::new(
+                    x + scale * (finger * 0.2f32 - 0.4f32),
+                    0.5f32 - scale * (joint * (1f32 - bend) + 0.3f32),
+                    -scale * joint * bend,
+                )
+            })
+            .collect()
+    }
+
+    fn learned(examples: Vec<Vec<f32>>) -> Sign {
+        let mut sign = Sign::default();
+        sign.mode = SignMode::Learned;
+        sign.examples = examples;
+
+        sign
+    }
+
+    #[test]
+    fn nearest_examples() {
+        let right = Handedness::Right;
+        let open = learned(vec![normalize(&hand(0f32, 0.5f32, 0.2f32), right); 5]);
+        let bent = learned(vec![normalize(&hand(0.8f32, 0.5f32, 0.2f32), right); 5]);
+        let features = Sign::default();
+        let classifier = Classifier::default();
+
+        // Elsewhere in the frame and closer to the camera
+        let likelihoods = classifier.likelihoods(
+            &[&open, &bent, &features],
+            &normalize(&hand(0.05f32, 0.3f32, 0.3f32), right),
+        );
+        assert!(likelihoods[0] > 0.6f32);
+        assert_eq!(likelihoods[1], 0f32);
+        assert_eq!(likelihoods[2], 0f32);
+
+        // Nothing alike
+        let likelihoods = classifier.likelihoods(
+            &[&open, &bent],
+            &normalize(&hand(0.4f32, 0.5f32, 0.2f32), right),
+        );
+        assert!(likelihoods.iter().all(|likelihood| *likelihood < 0.6f32));
+    }
+
+    #[test]
+    fn mirrored_left_hands() {
+        let right = normalize(&hand(0.5f32, 0.5f32, 0.2f32), Handedness::Right);
+        let mirrored: Vec<Point3F> = hand(0.5f32, 0.5f32, 0.2f32)
+            .iter()
+            .map(|landmark| Point3F::new(1f32 - landmark.x, landmark.y, landmark.z))
+            .collect();
+        let left = normalize(&mirrored, Handedness::Left);
+
+        assert!(right
+            .iter()
+            .zip(left.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-4));
+    }
+}
@@ -1,133 +1,718 @@
-use std::process::Command as ProcessCommand;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumDiscriminants, EnumString, EnumVariantNames};
-use tfc::MouseContext;
 
-use crate::common::PointerTracker;
-
-#[derive(Serialize, Deserialize, Display, EnumDiscriminants, EnumVariantNames)]
+#[derive(Serialize, Deserialize, Debug, Clone, Display, EnumDiscriminants, EnumVariantNames)]
 #[strum_discriminants(derive(Display, EnumString))]
 pub enum Command {
     Disabled,
-    Execute(String),
+    Execute(ExecCommand),
     Mouse(MouseButton, KeyEvent),
+    Key(Key, KeyEvent),
+    Shortcut(Shortcut),
+    Type(String),
     Scroll(ScrollCommand),
+    Sequence(Vec<Step>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(from = "ExecCommandRepr")]
+pub struct ExecCommand {
+    // Parsed like a POSIX shell would split the arguments
+    pub command: String,
+    // Run through /bin/sh -c instead, for pipes, redirections and globbing
+    pub shell: bool,
+    pub working_dir: Option<String>,
+    pub env: BTreeMap<String, String>,
+    // Seconds before the program is killed, none to let it run
+    pub timeout: Option<u64>,
+}
+
+impl From<String> for ExecCommand {
+    fn from(command: String) -> Self {
+        ExecCommand {
+            command,
+            ..ExecCommand::default()
+        }
+    }
+}
+
+// Older configurations store the command line only
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExecCommandRepr {
+    Plain(String),
+    Full {
+        command: String,
+        #[serde(default)]
+        shell: bool,
+        #[serde(default)]
+        working_dir: Option<String>,
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        #[serde(default)]
+        timeout: Option<u64>,
+    },
+}
+
+impl From<ExecCommandRepr> for ExecCommand {
+    fn from(repr: ExecCommandRepr) -> Self {
+        match repr {
+            ExecCommandRepr::Plain(command) => command.into(),
+            ExecCommandRepr::Full {
+                command,
+                shell,
+                working_dir,
+                env,
+                timeout,
+            } => ExecCommand {
+                command,
+                shell,
+                working_dir,
+                env,
+                timeout,
+            },
+        }
+    }
 }
 
 #[derive(
-    Serialize, Deserialize, Display, EnumVariantNames, EnumString, Clone, Copy, PartialEq, Eq,
+    Serialize, Deserialize, Debug, Display, EnumVariantNames, EnumString, Clone, Copy, PartialEq, Eq,
 )]
 pub enum Axis {
     X,
     Y,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ScrollCommand {
     pub custom_command: Option<String>,
     pub factor: f32,
     pub axis: Axis,
 }
 
-struct ProcessCommandSplitter {
-    command: String,
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Display, EnumVariantNames, EnumString)]
+pub enum KeyEvent {
+    Press,
+    Release,
+    Click,
 }
 
-impl From<String> for ProcessCommandSplitter {
-    fn from(command: String) -> Self {
-        Self { command }
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Display,
+    EnumVariantNames,
+    EnumString,
+)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl From<&MouseButton> for tfc::MouseButton {
+    fn from(button: &MouseButton) -> Self {
+        (*button).into()
     }
 }
 
-impl From<ProcessCommandSplitter> for String {
-    fn from(command: ProcessCommandSplitter) -> Self {
-        command.command
+impl From<MouseButton> for tfc::MouseButton {
+    fn from(button: MouseButton) -> Self {
+        match button {
+            MouseButton::Left => tfc::MouseButton::Left,
+            MouseButton::Right => tfc::MouseButton::Right,
+            MouseButton::Middle => tfc::MouseButton::Middle,
+        }
     }
 }
 
-impl From<ProcessCommandSplitter> for ProcessCommand {
-    fn from(command: ProcessCommandSplitter) -> Self {
-        let command = String::from(command);
-        let mut args = command.trim().split(' ');
-        let mut command = ProcessCommand::new(args.next().unwrap());
+// Declares the supported keys along with their tfc counterparts
+macro_rules! keys {
+    ($($key:ident),* $(,)?) => {
+        #[derive(
+            Serialize,
+            Deserialize,
+            Debug,
+            Copy,
+            Clone,
+            PartialEq,
+            Eq,
+            Hash,
+            Display,
+            EnumVariantNames,
+            EnumString,
+        )]
+        #[strum(ascii_case_insensitive)]
+        pub enum Key {
+            $($key),*
+        }
 
-        for arg in args {
-            command.arg(arg);
+        impl From<Key> for tfc::Key {
+            fn from(key: Key) -> Self {
+                match key {
+                    $(Key::$key => tfc::Key::$key),*
+                }
+            }
         }
+    };
+}
+
+keys!(
+    Control,
+    Shift,
+    Alt,
+    Meta,
+    RightControl,
+    RightShift,
+    RightAlt,
+    RightMeta,
+    CapsLock,
+    ReturnOrEnter,
+    Escape,
+    DeleteOrBackspace,
+    ForwardDelete,
+    Tab,
+    Space,
+    Minus,
+    Equal,
+    LeftBracket,
+    RightBracket,
+    Backslash,
+    Semicolon,
+    Quote,
+    Grave,
+    Comma,
+    Period,
+    Slash,
+    UpArrow,
+    RightArrow,
+    DownArrow,
+    LeftArrow,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    N0,
+    N1,
+    N2,
+    N3,
+    N4,
+    N5,
+    N6,
+    N7,
+    N8,
+    N9,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    PlayPause,
+    FastForward,
+    Rewind,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+);
+
+// Keys pressed in order and released in reverse, e.g. Control+Shift+T
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Shortcut(pub Vec<Key>);
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keys: Vec<String> = self.0.iter().map(Key::to_string).collect();
+
+        write!(f, "{}", keys.join("+"))
+    }
+}
+
+impl FromStr for Shortcut {
+    type Err = strum::ParseError;
 
-        command
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split('+')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(|key| match key.to_ascii_lowercase().as_str() {
+                // Common spellings that differ from the tfc key names
+                "ctrl" => Ok(Key::Control),
+                "super" | "win" => Ok(Key::Meta),
+                "enter" => Ok(Key::ReturnOrEnter),
+                "esc" => Ok(Key::Escape),
+                _ => Key::from_str(key),
+            })
+            .collect::<Result<Vec<Key>, _>>()
+            .map(Shortcut)
     }
 }
 
-impl Command {
-    pub fn execute(&self, pointer: &mut PointerTracker) {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Step {
+    Command(Command),
+    // Milliseconds
+    Delay(u64),
+}
+
+// The single line form used by the command editor, e.g. "Key T Click", "Delay 250",
+// "Scroll Y 100 [custom command]" or "Sequence [Key A; Key B]"
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Command::Disabled => (),
-            Command::Execute(cmd) => {
-                ProcessCommand::from(ProcessCommandSplitter::from(cmd.clone()))
-                    .spawn()
-                    .ok();
-            }
-            Command::Mouse(button, event) => {
-                match event {
-                    KeyEvent::Press => pointer.context_mut().mouse_down(button.into()).ok(),
-                    KeyEvent::Release => pointer.context_mut().mouse_up(button.into()).ok(),
-                    KeyEvent::Click => pointer.context_mut().mouse_click(button.into()).ok(),
-                };
+            Step::Delay(ms) => write!(f, "Delay {}", ms),
+            Step::Command(Command::Disabled) => write!(f, "Disabled"),
+            Step::Command(Command::Execute(cmd)) => {
+                write!(f, "Execute {}", escape_step(&exec_step(cmd)))
             }
-            Command::Scroll(cmd) => {
-                let offset_x: i32 = (pointer.delta_x() * cmd.factor).round() as i32;
-                let offset_y: i32 = (pointer.delta_y() * cmd.factor).round() as i32;
-
-                if let Some(ref custom_cmd) = cmd.custom_command {
-                    ProcessCommand::from(ProcessCommandSplitter::from(custom_cmd.clone()))
-                        .arg(
-                            match cmd.axis {
-                                Axis::X => offset_x,
-                                Axis::Y => offset_y,
-                            }
-                            .to_string(),
-                        )
-                        .spawn()
-                        .ok();
-                } else {
-                    match cmd.axis {
-                        Axis::X => pointer.context_mut().mouse_scroll(offset_x, 0).ok(),
-                        Axis::Y => pointer.context_mut().mouse_scroll(0, offset_y).ok(),
-                    };
+            Step::Command(Command::Mouse(button, event)) => write!(f, "Mouse {} {}", button, event),
+            Step::Command(Command::Key(key, event)) => write!(f, "Key {} {}", key, event),
+            Step::Command(Command::Shortcut(shortcut)) => write!(f, "Shortcut {}", shortcut),
+            Step::Command(Command::Type(text)) => write!(f, "Type {}", escape_step(text)),
+            Step::Command(Command::Scroll(cmd)) => {
+                write!(f, "Scroll {} {}", cmd.axis, cmd.factor)?;
+                match cmd.custom_command {
+                    Some(ref custom_command) => write!(f, " {}", escape_step(custom_command)),
+                    None => Ok(()),
                 }
             }
+            Step::Command(Command::Sequence(steps)) => {
+                write!(f, "Sequence [{}]", steps_to_string(steps))
+            }
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Display, EnumVariantNames, EnumString)]
-pub enum KeyEvent {
-    Press,
-    Release,
-    Click,
+impl FromStr for Step {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, value) = s.split_once(' ').unwrap_or((s, ""));
+        let value = value.trim();
+
+        if kind == "Delay" {
+            return value
+                .parse()
+                .map(Step::Delay)
+                .map_err(|_| strum::ParseError::VariantNotFound);
+        }
+
+        // "<button or key> [event]", the event defaults to a click
+        let mut args = value.split_whitespace();
+        let name = args.next().unwrap_or_default();
+        let mut event = || match args.next() {
+            Some(event) => KeyEvent::from_str(event),
+            None => Ok(KeyEvent::Click),
+        };
+
+        let command = match CommandDiscriminants::from_str(kind)? {
+            CommandDiscriminants::Disabled if value.is_empty() => Command::Disabled,
+            CommandDiscriminants::Disabled => return Err(strum::ParseError::VariantNotFound),
+            CommandDiscriminants::Execute => Command::Execute(
+                exec_from_step(&unescape_step(value)).ok_or(strum::ParseError::VariantNotFound)?,
+            ),
+            CommandDiscriminants::Mouse => Command::Mouse(MouseButton::from_str(name)?, event()?),
+            CommandDiscriminants::Key => Command::Key(Key::from_str(name)?, event()?),
+            CommandDiscriminants::Shortcut => Command::Shortcut(Shortcut::from_str(value)?),
+            CommandDiscriminants::Type => Command::Type(unescape_step(value)),
+            // "<axis> <factor> [custom command]"
+            CommandDiscriminants::Scroll => {
+                let mut args = value.splitn(3, ' ');
+                let axis = Axis::from_str(args.next().unwrap_or_default())?;
+                let factor = args
+                    .next()
+                    .unwrap_or_default()
+                    .parse()
+                    .map_err(|_| strum::ParseError::VariantNotFound)?;
+                let custom_command = args
+                    .next()
+                    .map(|custom_command| unescape_step(custom_command.trim()))
+                    .filter(|custom_command| !custom_command.is_empty());
+
+                Command::Scroll(ScrollCommand {
+                    custom_command,
+                    factor,
+                    axis,
+                })
+            }
+            CommandDiscriminants::Sequence => match value
+                .strip_prefix('[')
+                .and_then(|steps| steps.strip_suffix(']'))
+            {
+                Some(steps) => Command::Sequence(steps_from_str(steps)?),
+                None => return Err(strum::ParseError::VariantNotFound),
+            },
+        };
+
+        Ok(Step::Command(command))
+    }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Display, EnumVariantNames, EnumString)]
-pub enum MouseButton {
-    Left,
-    Right,
-    Middle,
+// The options go in braces before the command line, quoted like shell arguments, e.g.
+// "{shell timeout=5 dir='/my dir' env=LANG=C} ls | head"
+fn exec_step(cmd: &ExecCommand) -> String {
+    let mut options = Vec::new();
+    if cmd.shell {
+        options.push("shell".to_string());
+    }
+    if let Some(timeout) = cmd.timeout {
+        options.push(format!("timeout={}", timeout));
+    }
+    if let Some(ref working_dir) = cmd.working_dir {
+        options.push(format!("dir={}", shell_words::quote(working_dir)));
+    }
+    for (name, value) in &cmd.env {
+        options.push(format!(
+            "env={}={}",
+            shell_words::quote(name),
+            shell_words::quote(value)
+        ));
+    }
+
+    // A command starting with a brace gets empty options so it is not read as them
+    if options.is_empty() && !cmd.command.starts_with('{') {
+        cmd.command.clone()
+    } else {
+        format!("{{{}}} {}", options.join(" "), cmd.command)
+    }
 }
 
-impl From<&MouseButton> for tfc::MouseButton {
-    fn from(button: &MouseButton) -> Self {
-        (*button).into()
+fn exec_from_step(value: &str) -> Option<ExecCommand> {
+    let (options, command) = match value.strip_prefix('{') {
+        Some(rest) => {
+            let end = closing_brace(rest)?;
+            (&rest[..end], rest[end + 1..].trim())
+        }
+        None => ("", value),
+    };
+    let mut cmd = ExecCommand::from(command.to_string());
+
+    for option in shell_words::split(options).ok()? {
+        if option == "shell" {
+            cmd.shell = true;
+        } else if let Some(timeout) = option.strip_prefix("timeout=") {
+            cmd.timeout = Some(timeout.parse().ok()?);
+        } else if let Some(working_dir) = option.strip_prefix("dir=") {
+            cmd.working_dir = Some(working_dir.to_string());
+        } else if let Some((name, value)) = option
+            .strip_prefix("env=")
+            .and_then(|var| var.split_once('='))
+        {
+            cmd.env.insert(name.to_string(), value.to_string());
+        } else {
+            return None;
+        }
     }
+
+    Some(cmd)
 }
 
-impl From<MouseButton> for tfc::MouseButton {
-    fn from(button: MouseButton) -> Self {
-        match button {
-            MouseButton::Left => tfc::MouseButton::Left,
-            MouseButton::Right => tfc::MouseButton::Right,
-            MouseButton::Middle => tfc::MouseButton::Middle,
+// The first closing brace outside of quotes
+fn closing_brace(options: &str) -> Option<usize> {
+    let mut single = false;
+    let mut double = false;
+    let mut chars = options.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if !single => {
+                chars.next();
+            }
+            '\'' if !double => single = !single,
+            '"' if !single => double = !double,
+            '}' if !single && !double => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// Newlines can not be entered in a line edit, they are written as \n and a backslash as \\
+pub fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+// Other backslashes are kept as they are, e.g. in C:\temp
+pub fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                unescaped.push('\\');
+            }
+            _ => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+// Steps are separated by semicolons and nested in brackets, newlines can not be entered in a
+// line edit
+fn escape_step(value: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in value.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\\' | ';' | '[' | ']' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
         }
     }
+
+    escaped
+}
+
+// Like unescape_text, other backslashes are kept as they are
+fn unescape_step(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            ('\\', Some(&escaped)) if "\\;[]".contains(escaped) => {
+                chars.next();
+                unescaped.push(escaped);
+            }
+            _ => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+pub fn steps_to_string(steps: &[Step]) -> String {
+    let steps: Vec<String> = steps.iter().map(Step::to_string).collect();
+
+    steps.join("; ")
+}
+
+pub fn steps_from_str(s: &str) -> Result<Vec<Step>, strum::ParseError> {
+    let mut steps = Vec::new();
+    let mut step = String::new();
+    // Separators inside a nested sequence belong to it
+    let mut depth = 0usize;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Keep escaped characters for unescape_step
+            '\\' => {
+                step.push(c);
+                step.extend(chars.next());
+            }
+            '[' => {
+                depth += 1;
+                step.push(c);
+            }
+            ']' => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or(strum::ParseError::VariantNotFound)?;
+                step.push(c);
+            }
+            ';' if depth == 0 => steps.push(std::mem::take(&mut step)),
+            _ => step.push(c),
+        }
+    }
+    steps.push(step);
+
+    steps
+        .iter()
+        .filter(|step| !step.trim().is_empty())
+        .map(|step| Step::from_str(step))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::common::command::{escape_text, steps_from_str, steps_to_string, unescape_text};
+    use crate::common::{Axis, Command, ExecCommand, Key, KeyEvent, ScrollCommand, Shortcut, Step};
+
+    #[test]
+    fn parse_shortcut() {
+        let shortcut = Shortcut::from_str("control + Shift+T").unwrap();
+
+        assert_eq!(shortcut, Shortcut(vec![Key::Control, Key::Shift, Key::T]));
+        assert_eq!(shortcut.to_string(), "Control+Shift+T");
+        assert_eq!(Shortcut::from_str("Ctrl+Shift+T").unwrap(), shortcut);
+    }
+
+    #[test]
+    fn parse_invalid_shortcut() {
+        assert!(Shortcut::from_str("Control+Hyper").is_err());
+    }
+
+    #[test]
+    fn exec_command_from_plain_string() {
+        let command: Command = serde_yaml::from_str("Execute: notify-send 'Hello there'").unwrap();
+
+        assert!(matches!(command, Command::Execute(ref cmd)
+            if cmd.command == "notify-send 'Hello there'" && !cmd.shell && cmd.env.is_empty()));
+
+        let yaml = serde_yaml::to_string(&command).unwrap();
+        let command: Command = serde_yaml::from_str(&yaml).unwrap();
+        assert!(
+            matches!(command, Command::Execute(ref cmd) if cmd.command == "notify-send 'Hello there'")
+        );
+    }
+
+    #[test]
+    fn steps_round_trip() {
+        let text =
+            "Key Control Press; Delay 250; Mouse Left Click; Type a\\;b\\nc; Key Control Release";
+        let steps = steps_from_str(text).unwrap();
+
+        assert_eq!(steps.len(), 5);
+        assert!(matches!(steps[1], Step::Delay(250)));
+        assert!(matches!(steps[3], Step::Command(Command::Type(ref text)) if text == "a;b\nc"));
+        assert_eq!(steps_to_string(&steps), text);
+    }
+
+    #[test]
+    fn every_step_round_trips() {
+        let steps = vec![
+            Step::Command(Command::Disabled),
+            Step::Command(Command::Scroll(ScrollCommand {
+                custom_command: None,
+                factor: 1.5,
+                axis: Axis::Y,
+            })),
+            Step::Command(Command::Scroll(ScrollCommand {
+                custom_command: Some("xdotool click 4".to_string()),
+                factor: 100.0,
+                axis: Axis::X,
+            })),
+            Step::Command(Command::Sequence(vec![
+                Step::Command(Command::Type("[a; b]\\n".to_string())),
+                Step::Command(Command::Sequence(vec![Step::Delay(10)])),
+            ])),
+            Step::Command(Command::Execute("printf 'a\\tb;'".to_string().into())),
+        ];
+        let text = steps_to_string(&steps);
+        let parsed = steps_from_str(&text).unwrap();
+
+        assert_eq!(parsed.len(), steps.len());
+        assert_eq!(steps_to_string(&parsed), text);
+        assert!(matches!(parsed[0], Step::Command(Command::Disabled)));
+        assert!(matches!(parsed[2], Step::Command(Command::Scroll(ref cmd))
+            if cmd.custom_command.as_deref() == Some("xdotool click 4") && cmd.axis == Axis::X));
+        assert!(
+            matches!(parsed[3], Step::Command(Command::Sequence(ref nested))
+            if matches!(nested[0], Step::Command(Command::Type(ref text)) if text == "[a; b]\\n")
+                && matches!(nested[1], Step::Command(Command::Sequence(_))))
+        );
+        assert!(matches!(parsed[4], Step::Command(Command::Execute(ref cmd))
+            if cmd.command == "printf 'a\\tb;'"));
+        assert!(steps_from_str("Sequence [Key A; Key B").is_err());
+        assert!(steps_from_str("Key A]; Key B").is_err());
+    }
+
+    #[test]
+    fn exec_steps_keep_their_options() {
+        let mut cmd = ExecCommand::from("ls | head".to_string());
+        cmd.shell = true;
+        cmd.timeout = Some(5);
+        cmd.working_dir = Some("/my {dir}".to_string());
+        cmd.env.insert("LANG".to_string(), "C".to_string());
+        let step = Step::Command(Command::Execute(cmd.clone()));
+
+        assert_eq!(
+            step.to_string(),
+            "Execute {shell timeout=5 dir='/my {dir}' env=LANG=C} ls | head"
+        );
+        assert!(matches!(Step::from_str(&step.to_string()).unwrap(),
+            Step::Command(Command::Execute(ref parsed)) if *parsed == cmd));
+
+        let braces = Step::Command(Command::Execute("{ ls; }".to_string().into()));
+        assert_eq!(braces.to_string(), "Execute {} { ls\\; }");
+        assert!(matches!(Step::from_str(&braces.to_string()).unwrap(),
+            Step::Command(Command::Execute(ref parsed)) if parsed.command == "{ ls; }" && !parsed.shell));
+        assert!(matches!(Step::from_str("Execute notify-send hi").unwrap(),
+            Step::Command(Command::Execute(ref parsed)) if parsed.command == "notify-send hi"));
+        assert!(Step::from_str("Execute {retries=3} ls").is_err());
+    }
+
+    #[test]
+    fn escaped_text() {
+        let text = "first\nsecond \\n third";
+
+        assert_eq!(escape_text(text), "first\\nsecond \\\\n third");
+        assert_eq!(unescape_text(&escape_text(text)), text);
+        assert_eq!(unescape_text("C:\\temp\\"), "C:\\temp\\");
+        assert_eq!(unescape_text("trailing \\"), "trailing \\");
+        assert_eq!(unescape_text(&escape_text("C:\\new\\")), "C:\\new\\");
+    }
+
+    #[test]
+    fn step_event_defaults_to_click() {
+        let step = Step::from_str("Key T").unwrap();
+
+        assert!(matches!(
+            step,
+            Step::Command(Command::Key(Key::T, KeyEvent::Click))
+        ));
+        assert!(Step::from_str("Delay soon").is_err());
+        assert!(Step::from_str("Scroll X").is_err());
+    }
 }
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::io::Read;
+use std::process::Child;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Only the end of the error output is kept
+const MAX_STDERR_LEN: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandStatus {
+    Success,
+    // The exit code, none if killed by a signal
+    Failed(Option<i32>),
+    TimedOut(Duration),
+    // Unable to parse or start the command
+    Error(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandLog {
+    pub time: SystemTime,
+    pub command: String,
+    pub status: CommandStatus,
+    pub stderr: String,
+}
+
+impl CommandLog {
+    pub fn new(command: String, status: CommandStatus, stderr: String) -> Self {
+        CommandLog {
+            time: SystemTime::now(),
+            command,
+            status,
+            stderr,
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.status != CommandStatus::Success
+    }
+}
+
+impl fmt::Display for CommandLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            CommandStatus::Success => write!(f, "`{}` succeeded", self.command)?,
+            CommandStatus::Failed(Some(code)) => {
+                write!(f, "`{}` exited with {}", self.command, code)?
+            }
+            CommandStatus::Failed(None) => write!(f, "`{}` was killed", self.command)?,
+            CommandStatus::TimedOut(timeout) => write!(
+                f,
+                "`{}` timed out after {}s",
+                self.command,
+                timeout.as_secs_f32()
+            )?,
+            CommandStatus::Error(ref e) => write!(f, "`{}` could not run: {}", self.command, e)?,
+        }
+
+        match self.stderr.trim().lines().last() {
+            Some(line) => write!(f, ": {}", line),
+            None => Ok(()),
+        }
+    }
+}
+
+// Kills the whole process group, so programs started by a shell go too and release stderr
+fn kill(child: &mut Child) {
+    // The child leads its group when spawned with process_group(0)
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    child.kill().ok();
+}
+
+// Reaps the child on its own thread and reports how it went, the child should be spawned in
+// its own process group
+pub fn watch(
+    mut child: Child,
+    command: String,
+    timeout: Option<Duration>,
+    quiet: bool,
+    log: Sender<CommandLog>,
+) {
+    thread::spawn(move || {
+        // Read concurrently, a full pipe would block the child
+        let stderr = child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut output = String::new();
+                stderr.read_to_string(&mut output).ok();
+                output
+            })
+        });
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => break CommandStatus::Success,
+                Ok(Some(status)) => break CommandStatus::Failed(status.code()),
+                Ok(None) => match timeout {
+                    Some(timeout) if started.elapsed() >= timeout => {
+                        kill(&mut child);
+                        child.wait().ok();
+                        break CommandStatus::TimedOut(timeout);
+                    }
+                    Some(_) => thread::sleep(POLL_INTERVAL),
+                    None => {
+                        break match child.wait() {
+                            Ok(status) if status.success() => CommandStatus::Success,
+                            Ok(status) => CommandStatus::Failed(status.code()),
+                            Err(e) => CommandStatus::Error(e.to_string()),
+                        }
+                    }
+                },
+                Err(e) => break CommandStatus::Error(e.to_string()),
+            }
+        };
+
+        if quiet && status == CommandStatus::Success {
+            return;
+        }
+
+        let mut stderr = stderr
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        if stderr.len() > MAX_STDERR_LEN {
+            let mut start = stderr.len() - MAX_STDERR_LEN;
+            while !stderr.is_char_boundary(start) {
+                start += 1;
+            }
+            stderr.drain(..start);
+        }
+
+        log.send(CommandLog::new(command, status, stderr)).ok();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use crate::common::command_log::{watch, CommandLog, CommandStatus};
+
+    fn run(script: &str, timeout: Option<Duration>) -> CommandLog {
+        let (sender, receiver) = mpsc::channel();
+        let child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(script)
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .unwrap();
+
+        watch(child, script.to_string(), timeout, false, sender);
+
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn captures_exit_code_and_stderr() {
+        let log = run("echo oops >&2; exit 3", None);
+
+        assert_eq!(log.status, CommandStatus::Failed(Some(3)));
+        assert_eq!(log.stderr.trim(), "oops");
+        assert_eq!(
+            log.to_string(),
+            "`echo oops >&2; exit 3` exited with 3: oops"
+        );
+    }
+
+    #[test]
+    fn kills_after_timeout() {
+        let log = run("exec sleep 10", Some(Duration::from_millis(200)));
+
+        assert_eq!(
+            log.status,
+            CommandStatus::TimedOut(Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn kills_programs_started_by_the_shell() {
+        let log = run("sleep 10; true", Some(Duration::from_millis(200)));
+
+        assert_eq!(
+            log.status,
+            CommandStatus::TimedOut(Duration::from_millis(200))
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashSet;
+use std::os::unix::process::CommandExt;
+use std::process::{Command as ProcessCommand, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tfc::{Context, Key, KeyboardContext, MouseContext, UnicodeKeyboardContext};
+
+use crate::common::command_log::{self, CommandLog, CommandStatus};
+use crate::common::{
+    steps_to_string, Axis, Command, ExecCommand, Key as CommandKey, KeyEvent, MouseButton,
+    PointerTracker, Step,
+};
+
+// Granularity of the delays, so a stopped sequence does not linger
+const DELAY_STEP: Duration = Duration::from_millis(50);
+
+// What triggered a command, exposed to executed processes through environment variables
+#[derive(Clone, Default)]
+struct Gesture {
+    state: String,
+    sign: Option<String>,
+    // Virtual coordinates, range: [0, 1]
+    pointer: (f32, f32),
+    delta: (f32, f32),
+}
+
+// Mouse buttons and keys pressed but not released yet
+#[derive(Default)]
+struct Held {
+    buttons: HashSet<MouseButton>,
+    keys: HashSet<CommandKey>,
+}
+
+// Shared between the recognition thread and the sequence threads
+#[derive(Clone)]
+struct Shared {
+    // Results of the executed programs, reported from their watcher threads
+    log: Sender<CommandLog>,
+    held: Arc<Mutex<Held>>,
+    // Set while a sequence is running on its own thread
+    sequence_running: Arc<AtomicBool>,
+    // Asks the running sequence to stop before its next step
+    sequence_cancelled: Arc<AtomicBool>,
+}
+
+pub struct CommandExecutor {
+    // Log the commands instead of executing them
+    dry_run: bool,
+
+    state: String,
+    sign: Option<String>,
+
+    shared: Shared,
+    log_receiver: Receiver<CommandLog>,
+}
+
+impl CommandExecutor {
+    pub fn new(dry_run: bool) -> Self {
+        let (log, log_receiver) = mpsc::channel();
+
+        CommandExecutor {
+            dry_run,
+            state: String::new(),
+            sign: None,
+            shared: Shared {
+                log,
+                held: Arc::new(Mutex::new(Held::default())),
+                sequence_running: Arc::new(AtomicBool::new(false)),
+                sequence_cancelled: Arc::new(AtomicBool::new(false)),
+            },
+            log_receiver,
+        }
+    }
+
+    // Emergency stop: cancels the running sequence and releases everything still held
+    pub fn stop(&mut self, pointer: &mut PointerTracker) {
+        self.shared.sequence_cancelled.store(true, Ordering::SeqCst);
+
+        let mut held = self.shared.held.lock().unwrap();
+        let context = pointer.context_mut();
+
+        for button in held.buttons.iter() {
+            context.mouse_up(button.into()).ok();
+        }
+        for key in held.keys.iter() {
+            context.key_up((*key).into()).ok();
+        }
+
+        held.buttons.clear();
+        held.keys.clear();
+    }
+
+    pub fn drain_log(&self) -> Vec<CommandLog> {
+        self.log_receiver.try_iter().collect()
+    }
+
+    pub fn set_state(&mut self, state: &str) {
+        self.state = state.to_string();
+    }
+
+    pub fn set_sign(&mut self, sign: Option<&str>) {
+        self.sign = sign.map(str::to_string);
+    }
+
+    pub fn execute(&mut self, command: &Command, pointer: &mut PointerTracker) {
+        let gesture = Gesture {
+            state: self.state.clone(),
+            sign: self.sign.clone(),
+            pointer: (pointer.x(), pointer.y()),
+            delta: (pointer.delta_x(), pointer.delta_y()),
+        };
+
+        if self.dry_run {
+            log(command, &gesture);
+            return;
+        }
+
+        match command {
+            Command::Sequence(steps) => self.spawn_sequence(steps.clone(), gesture),
+            _ => run(command, pointer.context_mut(), &gesture, &self.shared),
+        }
+    }
+
+    // Sequences may contain delays, so they run off the recognition thread
+    fn spawn_sequence(&self, steps: Vec<Step>, gesture: Gesture) {
+        // Drop the sequence if the previous one is still running, repeated gestures would pile up
+        if self.shared.sequence_running.swap(true, Ordering::SeqCst) {
+            let command = format!("Sequence {}", steps_to_string(&steps));
+            let status = CommandStatus::Error("The previous sequence is still running".to_string());
+            self.shared
+                .log
+                .send(CommandLog::new(command, status, String::new()))
+                .ok();
+            return;
+        }
+        self.shared
+            .sequence_cancelled
+            .store(false, Ordering::SeqCst);
+
+        let shared = self.shared.clone();
+        thread::spawn(move || {
+            // tfc contexts can not be shared between threads
+            match Context::new() {
+                Ok(mut context) => {
+                    thread::sleep(Duration::from_millis(10));
+                    run_steps(&steps, &mut context, &gesture, &shared);
+                }
+                Err(e) => eprintln!("ERROR: Unable to run the sequence: {:?}", e),
+            }
+
+            shared.sequence_running.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+fn run(command: &Command, context: &mut Context, gesture: &Gesture, shared: &Shared) {
+    match command {
+        Command::Disabled => (),
+        Command::Execute(cmd) => spawn(
+            &cmd.command,
+            process(cmd, gesture),
+            cmd.timeout.map(Duration::from_secs),
+            false,
+            &shared.log,
+        ),
+        Command::Mouse(button, event) => {
+            let mut held = shared.held.lock().unwrap();
+            match event {
+                KeyEvent::Press => {
+                    held.buttons.insert(*button);
+                    context.mouse_down(button.into()).ok()
+                }
+                KeyEvent::Release => {
+                    held.buttons.remove(button);
+                    context.mouse_up(button.into()).ok()
+                }
+                KeyEvent::Click => context.mouse_click(button.into()).ok(),
+            };
+        }
+        Command::Key(key, event) => {
+            let mut held = shared.held.lock().unwrap();
+            match event {
+                KeyEvent::Press => {
+                    held.keys.insert(*key);
+                    context.key_down((*key).into()).ok()
+                }
+                KeyEvent::Release => {
+                    held.keys.remove(key);
+                    context.key_up((*key).into()).ok()
+                }
+                KeyEvent::Click => context.key_click((*key).into()).ok(),
+            };
+        }
+        Command::Shortcut(shortcut) => {
+            for key in shortcut.0.iter() {
+                context.key_down((*key).into()).ok();
+            }
+            for key in shortcut.0.iter().rev() {
+                context.key_up((*key).into()).ok();
+            }
+        }
+        Command::Type(text) => type_text(text, context),
+        Command::Scroll(cmd) => {
+            let offset = scroll_offset(cmd.axis, cmd.factor, gesture.delta);
+
+            if let Some(ref custom_cmd) = cmd.custom_command {
+                let process = process(&custom_cmd.clone().into(), gesture);
+                let process = process.map(|mut process| {
+                    process.arg(offset.to_string());
+                    process
+                });
+
+                // Runs every frame, only failures are worth reporting
+                spawn(custom_cmd, process, None, true, &shared.log);
+            } else {
+                match cmd.axis {
+                    Axis::X => context.mouse_scroll(offset, 0).ok(),
+                    Axis::Y => context.mouse_scroll(0, offset).ok(),
+                };
+            }
+        }
+        // Nested sequences run inline on the thread of the outer one
+        Command::Sequence(steps) => run_steps(steps, context, gesture, shared),
+    }
+}
+
+fn run_steps(steps: &[Step], context: &mut Context, gesture: &Gesture, shared: &Shared) {
+    let cancelled = || shared.sequence_cancelled.load(Ordering::SeqCst);
+
+    for step in steps {
+        if cancelled() {
+            return;
+        }
+
+        match step {
+            Step::Command(command) => run(command, context, gesture, shared),
+            Step::Delay(ms) => {
+                let mut remaining = Duration::from_millis(*ms);
+                while !remaining.is_zero() && !cancelled() {
+                    let delay = remaining.min(DELAY_STEP);
+                    thread::sleep(delay);
+                    remaining -= delay;
+                }
+            }
+        }
+    }
+}
+
+fn log(command: &Command, gesture: &Gesture) {
+    match command {
+        Command::Disabled => (),
+        Command::Scroll(cmd) => {
+            // Scrolling runs every frame, only report actual movement
+            let offset = scroll_offset(cmd.axis, cmd.factor, gesture.delta);
+            if offset != 0 {
+                println!("Dry run: Scroll {} by {}", cmd.axis, offset);
+            }
+        }
+        Command::Sequence(steps) => println!("Dry run: Sequence {}", steps_to_string(steps)),
+        _ => println!("Dry run: {:?}", command),
+    }
+}
+
+fn process(cmd: &ExecCommand, gesture: &Gesture) -> Result<ProcessCommand, String> {
+    let mut process = if cmd.shell {
+        let mut process = ProcessCommand::new("/bin/sh");
+        process.arg("-c").arg(&cmd.command);
+        process
+    } else {
+        let args = shell_words::split(&cmd.command)
+            .map_err(|e| format!("Unable to parse `{}`: {}", cmd.command, e))?;
+        let (program, args) = args
+            .split_first()
+            .ok_or_else(|| String::from("Empty command"))?;
+
+        let mut process = ProcessCommand::new(program);
+        process.args(args);
+        process
+    };
+
+    process
+        .env("ANTHON_STATE", &gesture.state)
+        .env("ANTHON_SIGN", gesture.sign.as_deref().unwrap_or_default())
+        .env("ANTHON_POINTER_X", gesture.pointer.0.to_string())
+        .env("ANTHON_POINTER_Y", gesture.pointer.1.to_string())
+        .envs(&cmd.env);
+
+    if let Some(ref working_dir) = cmd.working_dir {
+        process.current_dir(working_dir);
+    }
+
+    Ok(process)
+}
+
+fn spawn(
+    command: &str,
+    process: Result<ProcessCommand, String>,
+    timeout: Option<Duration>,
+    quiet: bool,
+    log: &Sender<CommandLog>,
+) {
+    let child = process.and_then(|mut process| {
+        process
+            .stdin(Stdio::null())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .map_err(|e| e.to_string())
+    });
+
+    match child {
+        Ok(child) => command_log::watch(child, command.to_string(), timeout, quiet, log.clone()),
+        Err(e) => {
+            let status = CommandStatus::Error(e);
+            log.send(CommandLog::new(command.to_string(), status, String::new()))
+                .ok();
+        }
+    }
+}
+
+// Line breaks and tabs are sent as key presses, unicode input does not handle them everywhere
+fn type_text(text: &str, context: &mut Context) {
+    let mut chunk = String::new();
+
+    for c in text.chars() {
+        let key = match c {
+            '\n' => Key::ReturnOrEnter,
+            '\t' => Key::Tab,
+            // Windows line endings, the newline already presses enter
+            '\r' => continue,
+            _ => {
+                chunk.push(c);
+                continue;
+            }
+        };
+
+        if !chunk.is_empty() {
+            context.unicode_string(&chunk).ok();
+            chunk.clear();
+        }
+        context.key_click(key).ok();
+    }
+
+    if !chunk.is_empty() {
+        context.unicode_string(&chunk).ok();
+    }
+}
+
+fn scroll_offset(axis: Axis, factor: f32, delta: (f32, f32)) -> i32 {
+    let delta = match axis {
+        Axis::X => delta.0,
+        Axis::Y => delta.1,
+    };
+
+    (delta * factor).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use crate::common::command_log::CommandStatus;
+    use crate::common::executor::{CommandExecutor, Gesture};
+    use crate::common::{Command, Key, KeyEvent, Step};
+
+    #[test]
+    fn skipped_sequences_are_logged() {
+        let executor = CommandExecutor::new(false);
+        let steps = vec![Step::Command(Command::Key(Key::A, KeyEvent::Click))];
+        executor
+            .shared
+            .sequence_running
+            .store(true, Ordering::SeqCst);
+
+        executor.spawn_sequence(steps, Gesture::default());
+        let log = executor.drain_log();
+
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].command, "Sequence Key A Click");
+        assert!(matches!(log[0].status, CommandStatus::Error(_)));
+        assert!(executor.shared.sequence_running.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,530 @@
+use serde::{Deserialize, Serialize};
+use strum::EnumCount;
+use strum_macros::{Display, EnumString, EnumVariantNames};
+
+use crate::common::{Circle, Feature, Handedness, Point2F, Point3F, Sign, Vec2F};
+
+// Fingertips of the index, middle, ring and pinky fingers
+const FINGERTIPS: [usize; 4] = [8, 12, 16, 20];
+// Landmarks of a hand, as tracked by Mediapipe
+pub const HAND_LANDMARKS: usize = 21;
+// How quickly a score goes from 0 to 1 around the middle of the thresholds, per their distance
+const SCORE_STEEPNESS: f32 = 2f32;
+
+// A feature turns on past `on` and only turns back off past `off`, so it doesn't flicker in between.
+// Rising when `on` is above `off`, falling otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Hysteresis {
+    pub on: f32,
+    pub off: f32,
+}
+
+impl Hysteresis {
+    pub fn update(&self, value: f32, previous: bool) -> bool {
+        match (previous, self.on >= self.off) {
+            (false, true) => value > self.on,
+            (true, true) => value > self.off,
+            (false, false) => value < self.on,
+            (true, false) => value < self.off,
+        }
+    }
+
+    // 0.5 at the threshold `update` switches at, towards 1 past it while off and past `on`, towards
+    // 0 past `off`
+    pub fn score(&self, value: f32, previous: bool) -> f32 {
+        let middle = if previous { self.off } else { self.on };
+        let band = if self.on >= self.off {
+            (self.on - self.off).max(f32::EPSILON)
+        } else {
+            (self.on - self.off).min(-f32::EPSILON)
+        };
+
+        1f32 / (1f32 + (-SCORE_STEEPNESS * (value - middle) / band).exp())
+    }
+}
+
+// How far apart both hands are
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Display,
+    EnumString,
+    EnumVariantNames,
+)]
+pub enum HandDistance {
+    Close,
+    Apart,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct FeatureConfig {
+    // Landmarks the palm circle goes through
+    pub palm_landmarks: [usize; 3],
+    // Distance of the fingertip to the palm center, in palm radii
+    pub finger_closed: Hysteresis,
+    // Degrees between the wrist to pinky line and the vertical
+    pub hand_rotated: Hysteresis,
+    // Degrees between neighbouring fingers
+    pub finger_spread: Hysteresis,
+    // Distance of the thumb tip to the palm center, in palm radii
+    pub thumb_closed: Hysteresis,
+    // Distance of the thumb tip to the other fingertips, in palm radii
+    pub fingertip_touch: Hysteresis,
+    // Degrees between the index finger and the direction it points to
+    pub pointing: Hysteresis,
+    // Sine of the angle from the index base to the pinky base around the wrist, negative from the back of the hand
+    pub palm_facing: Hysteresis,
+    // Depth of the index tip in front of its base, in palm widths
+    pub index_forward: Hysteresis,
+    // Distance between the hand centers, in palm widths
+    pub hands_close: Hysteresis,
+    pub hands_apart: Hysteresis,
+    // Lowest likelihood a sign is recognized with, from the scores of its features
+    pub min_likelihood: f32,
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        FeatureConfig {
+            palm_landmarks: [0, 5, 17],
+            finger_closed: Hysteresis {
+                on: 1f32,
+                off: 1.1f32,
+            },
+            hand_rotated: Hysteresis {
+                on: 140f32,
+                off: 150f32,
+            },
+            finger_spread: Hysteresis {
+                on: 10f32,
+                off: 8f32,
+            },
+            thumb_closed: Hysteresis {
+                on: 1f32,
+                off: 1.1f32,
+            },
+            fingertip_touch: Hysteresis {
+                on: 0.3f32,
+                off: 0.4f32,
+            },
+            pointing: Hysteresis {
+                on: 35f32,
+                off: 45f32,
+            },
+            palm_facing: Hysteresis {
+                on: 0.1f32,
+                off: -0.1f32,
+            },
+            index_forward: Hysteresis {
+                on: 0.6f32,
+                off: 0.45f32,
+            },
+            hands_close: Hysteresis {
+                on: 1.5f32,
+                off: 2f32,
+            },
+            hands_apart: Hysteresis {
+                on: 4f32,
+                off: 3.5f32,
+            },
+            min_likelihood: 0.6f32,
+        }
+    }
+}
+
+impl FeatureConfig {
+    // Catches hand edited configurations that would fail while measuring the hand
+    pub fn validate(&self) -> Result<(), String> {
+        match self.palm_landmarks.iter().find(|i| **i >= HAND_LANDMARKS) {
+            Some(i) => Err(format!(
+                "Invalid palm landmark {}, a hand has {} landmarks",
+                i, HAND_LANDMARKS
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // The features of the hand, the previous ones decide between the on and off thresholds
+    pub fn sign(
+        &self,
+        landmarks: &[Point3F],
+        handedness: Handedness,
+        previous: Option<&Sign>,
+    ) -> Sign {
+        let measurements = Measurements::new(landmarks, handedness, self.palm_landmarks);
+
+        self.sign_from(&measurements, previous)
+    }
+
+    pub fn hand_distance(
+        &self,
+        left: &[Point2F],
+        right: &[Point2F],
+        previous: Option<HandDistance>,
+    ) -> Option<HandDistance> {
+        let center = |hand: &[Point2F]| {
+            Point2F::new(
+                (hand[5].x + hand[17].x) / 2f32,
+                (hand[5].y + hand[17].y) / 2f32,
+            )
+        };
+        let palm_width = (left[5].distance(&left[17]) + right[5].distance(&right[17])) / 2f32;
+        let distance = center(left).distance(&center(right)) / palm_width.max(f32::EPSILON);
+
+        if self
+            .hands_close
+            .update(distance, previous == Some(HandDistance::Close))
+        {
+            Some(HandDistance::Close)
+        } else if self
+            .hands_apart
+            .update(distance, previous == Some(HandDistance::Apart))
+        {
+            Some(HandDistance::Apart)
+        } else {
+            None
+        }
+    }
+
+    pub fn sign_from(&self, measurements: &Measurements, previous: Option<&Sign>) -> Sign {
+        let mut sign = Sign::default();
+
+        for (feature, hysteresis, value) in self.values(measurements) {
+            let index = feature as usize;
+            let was_on =
+                matches!(previous, Some(previous) if previous.required_attributes().get(index));
+
+            sign.set_feature(index, false, hysteresis.update(value, was_on));
+        }
+
+        sign
+    }
+
+    // How clearly each feature is on, from 0 to 1, indexed like the features. As for the signs, the
+    // previous features decide between the on and off thresholds.
+    pub fn scores(&self, measurements: &Measurements, previous: Option<&Sign>) -> Vec<f32> {
+        let mut scores = vec![0.5f32; Feature::COUNT];
+
+        for (feature, hysteresis, value) in self.values(measurements) {
+            let index = feature as usize;
+            let was_on =
+                matches!(previous, Some(previous) if previous.required_attributes().get(index));
+
+            scores[index] = hysteresis.score(value, was_on);
+        }
+
+        scores
+    }
+
+    fn values(&self, measurements: &Measurements) -> [(Feature, Hysteresis, f32); Feature::COUNT] {
+        [
+            (
+                Feature::HandRotated,
+                self.hand_rotated,
+                measurements.hand_angle,
+            ),
+            (
+                Feature::IndexClosed,
+                self.finger_closed,
+                measurements.finger_distances[0],
+            ),
+            (
+                Feature::MiddleClosed,
+                self.finger_closed,
+                measurements.finger_distances[1],
+            ),
+            (
+                Feature::RingClosed,
+                self.finger_closed,
+                measurements.finger_distances[2],
+            ),
+            (
+                Feature::PinkyClosed,
+                self.finger_closed,
+                measurements.finger_distances[3],
+            ),
+            (
+                Feature::ThumbIndexSpread,
+                self.finger_spread,
+                measurements.spreads[0],
+            ),
+            (
+                Feature::IndexMiddleSpread,
+                self.finger_spread,
+                measurements.spreads[1],
+            ),
+            (
+                Feature::MiddleRingSpread,
+                self.finger_spread,
+                measurements.spreads[2],
+            ),
+            (
+                Feature::RingPinkySpread,
+                self.finger_spread,
+                measurements.spreads[3],
+            ),
+            (
+                Feature::ThumbClosed,
+                self.thumb_closed,
+                measurements.thumb_distance,
+            ),
+            (
+                Feature::ThumbIndexPinch,
+                self.fingertip_touch,
+                measurements.fingertip_distances[0],
+            ),
+            (
+                Feature::ThumbMiddleTouch,
+                self.fingertip_touch,
+                measurements.fingertip_distances[1],
+            ),
+            (
+                Feature::ThumbRingTouch,
+                self.fingertip_touch,
+                measurements.fingertip_distances[2],
+            ),
+            (
+                Feature::ThumbPinkyTouch,
+                self.fingertip_touch,
+                measurements.fingertip_distances[3],
+            ),
+            (
+                Feature::IndexPointingUp,
+                self.pointing,
+                measurements.index_angle,
+            ),
+            (
+                Feature::IndexPointingDown,
+                self.pointing,
+                180f32 - measurements.index_angle,
+            ),
+            (
+                Feature::IndexPointingSideways,
+                self.pointing,
+                (90f32 - measurements.index_angle).abs(),
+            ),
+            (
+                Feature::PalmFacing,
+                self.palm_facing,
+                measurements.palm_facing,
+            ),
+            (
+                Feature::IndexForward,
+                self.index_forward,
+                measurements.index_depth,
+            ),
+        ]
+    }
+}
+
+// The values the features are thresholded from
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Measurements {
+    pub hand_angle: f32,
+    // Index, middle, ring and pinky
+    pub finger_distances: [f32; 4],
+    // Thumb to index, index to middle, middle to ring and ring to pinky
+    pub spreads: [f32; 4],
+    pub thumb_distance: f32,
+    // Thumb tip to the index, middle, ring and pinky tips
+    pub fingertip_distances: [f32; 4],
+    // Degrees between the index finger and the top of the frame
+    pub index_angle: f32,
+    // Positive when the palm faces the camera, in the mirrored frame
+    pub palm_facing: f32,
+    // Positive when the index tip is closer to the camera than its base
+    pub index_depth: f32,
+}
+
+impl Measurements {
+    pub fn new(landmarks: &[Point3F], handedness: Handedness, palm_landmarks: [usize; 3]) -> Self {
+        let depths: Vec<f32> = landmarks.iter().map(|landmark| landmark.z).collect();
+        let landmarks: Vec<Point2F> = landmarks.iter().copied().map(Point2F::from).collect();
+        let palm_width = landmarks[5].distance(&landmarks[17]).max(f32::EPSILON);
+        let palm: Vec<Point2F> = palm_landmarks.iter().map(|i| landmarks[*i]).collect();
+        let palm_circle = Circle::from(palm.as_slice());
+        let to_index = Vec2F::from((landmarks[0], landmarks[5]));
+        let to_pinky = Vec2F::from((landmarks[0], landmarks[17]));
+        // The thumb of a left hand is on the other side
+        let side = match handedness {
+            Handedness::Left => -1f32,
+            Handedness::Right => 1f32,
+        };
+        let angle = |a: (usize, usize), b: (usize, usize)| {
+            Vec2F::from((landmarks[a.0], landmarks[a.1]))
+                .angle(&Vec2F::from((landmarks[b.0], landmarks[b.1])))
+                .to_degrees()
+        };
+
+        Measurements {
+            hand_angle: Vec2F::from((landmarks[0], landmarks[17]))
+                .angle(&Vec2F { x: 0f32, y: 1f32 })
+                .to_degrees(),
+            finger_distances: FINGERTIPS.map(|tip| palm_circle.relative_distance(&landmarks[tip])),
+            spreads: [
+                angle((0, 4), (0, 5)),
+                angle((5, 8), (9, 12)),
+                angle((9, 12), (13, 16)),
+                angle((13, 16), (17, 20)),
+            ],
+            thumb_distance: palm_circle.relative_distance(&landmarks[4]),
+            fingertip_distances: FINGERTIPS.map(|tip| {
+                landmarks[4].distance(&landmarks[tip]) / palm_circle.radius().max(f32::EPSILON)
+            }),
+            index_angle: Vec2F::from((landmarks[5], landmarks[8]))
+                .angle(&Vec2F { x: 0f32, y: -1f32 })
+                .to_degrees(),
+            palm_facing: side * to_index.cross(&to_pinky)
+                / (to_index.magnitude() * to_pinky.magnitude()).max(f32::EPSILON),
+            index_depth: (depths[5] - depths[8]) / palm_width,
+        }
+    }
+
+    pub fn mean(samples: &[Measurements]) -> Self {
+        let n = samples.len().max(1) as f32;
+        let mut mean = Measurements::default();
+
+        for sample in samples {
+            mean.hand_angle += sample.hand_angle / n;
+            mean.thumb_distance += sample.thumb_distance / n;
+            mean.index_angle += sample.index_angle / n;
+            mean.palm_facing += sample.palm_facing / n;
+            mean.index_depth += sample.index_depth / n;
+            for i in 0..4 {
+                mean.finger_distances[i] += sample.finger_distances[i] / n;
+                mean.spreads[i] += sample.spreads[i] / n;
+                mean.fingertip_distances[i] += sample.fingertip_distances[i] / n;
+            }
+        }
+
+        mean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::feature_config::{FeatureConfig, HandDistance, Hysteresis, Measurements};
+    use crate::common::{Feature, Point2F};
+
+    #[test]
+    fn hysteresis() {
+        let rising = Hysteresis {
+            on: 10f32,
+            off: 8f32,
+        };
+        assert!(!rising.update(9f32, false));
+        assert!(rising.update(9f32, true));
+        assert!(!rising.update(7f32, true));
+
+        let falling = Hysteresis {
+            on: 140f32,
+            off: 150f32,
+        };
+        assert!(falling.update(130f32, false));
+        assert!(!falling.update(145f32, false));
+        assert!(falling.update(145f32, true));
+    }
+
+    #[test]
+    fn palm_landmarks_within_the_hand() {
+        let mut config = FeatureConfig::default();
+        assert!(config.validate().is_ok());
+
+        config.palm_landmarks = [0, 5, 21];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn scores() {
+        let falling = Hysteresis {
+            on: 140f32,
+            off: 150f32,
+        };
+        assert!((falling.score(140f32, false) - 0.5f32).abs() < 1e-4);
+        assert!((falling.score(150f32, true) - 0.5f32).abs() < 1e-4);
+        assert!(falling.score(100f32, false) > 0.99f32);
+        assert!(falling.score(170f32, true) < 0.05f32);
+        // Between the thresholds the score follows the previous state
+        assert!(falling.score(145f32, false) < 0.5f32);
+        assert!(falling.score(145f32, true) > 0.5f32);
+
+        let config = FeatureConfig::default();
+        let measurements = Measurements {
+            hand_angle: 180f32,
+            finger_distances: [0.5f32, 1.05f32, 2f32, 2f32],
+            ..Measurements::default()
+        };
+        let scores = config.scores(&measurements, None);
+        assert!(scores[Feature::IndexClosed as usize] > 0.9f32);
+        assert!(scores[Feature::MiddleClosed as usize] < 0.5f32);
+        assert!(scores[Feature::RingClosed as usize] < 0.1f32);
+
+        let previous = config.sign_from(
+            &Measurements {
+                finger_distances: [0.5f32; 4],
+                ..measurements
+            },
+            None,
+        );
+        let scores = config.scores(&measurements, Some(&previous));
+        assert!(scores[Feature::MiddleClosed as usize] > 0.5f32);
+    }
+
+    #[test]
+    fn features_keep_their_state_between_thresholds() {
+        let config = FeatureConfig::default();
+        let index = Feature::IndexClosed as usize;
+        let measurements = |distance: f32| Measurements {
+            hand_angle: 180f32,
+            finger_distances: [distance, 2f32, 2f32, 2f32],
+            spreads: [20f32; 4],
+            ..Measurements::default()
+        };
+
+        let closed = config.sign_from(&measurements(0.9f32), None);
+        assert!(closed.required_attributes().get(index));
+
+        let between = config.sign_from(&measurements(1.05f32), Some(&closed));
+        assert!(between.required_attributes().get(index));
+        assert!(!config
+            .sign_from(&measurements(1.05f32), None)
+            .required_attributes()
+            .get(index));
+    }
+
+    #[test]
+    fn hand_distance() {
+        let config = FeatureConfig::default();
+        // Palms 0.1 wide, centered on x
+        let hand = |x: f32| {
+            let mut landmarks = vec![Point2F::new(x, 0.5f32); 21];
+            landmarks[5] = Point2F::new(x - 0.05f32, 0.5f32);
+            landmarks[17] = Point2F::new(x + 0.05f32, 0.5f32);
+            landmarks
+        };
+
+        assert_eq!(
+            config.hand_distance(&hand(0.4f32), &hand(0.52f32), None),
+            Some(HandDistance::Close)
+        );
+        assert_eq!(
+            config.hand_distance(&hand(0.4f32), &hand(0.58f32), Some(HandDistance::Close)),
+            Some(HandDistance::Close)
+        );
+        assert_eq!(
+            config.hand_distance(&hand(0.4f32), &hand(0.7f32), None),
+            None
+        );
+        assert_eq!(
+            config.hand_distance(&hand(0.2f32), &hand(0.8f32), None),
+            Some(HandDistance::Apart)
+        );
+    }
+}
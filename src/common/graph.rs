@@ -29,6 +29,10 @@ impl<I: Eq + Hash + Copy, N: Node<I>, E: Edge<I>> Graph<I, N, E> {
         &self.edges
     }
 
+    pub fn node_iter_mut(&mut self) -> IterMut<'_, I, N> {
+        self.nodes.iter_mut()
+    }
+
     pub fn edge_iter_mut(&mut self) -> IterMut<'_, I, HashMap<I, E>> {
         self.edges.iter_mut()
     }
@@ -1,26 +1,45 @@
 pub use bit_string::BitString;
+pub use calibration::{Calibration, CalibrationError, CalibrationPose};
 pub use circle::Circle;
-pub use command::{Axis, Command, CommandDiscriminants, KeyEvent, MouseButton, ScrollCommand};
+pub use classifier::Classifier;
+pub use command::{
+    escape_text, steps_from_str, steps_to_string, unescape_text, Axis, Command,
+    CommandDiscriminants, ExecCommand, Key, KeyEvent, MouseButton, ScrollCommand, Shortcut, Step,
+};
+pub use command_log::{CommandLog, CommandStatus};
+pub use executor::CommandExecutor;
+pub use feature_config::{FeatureConfig, HandDistance, Hysteresis, Measurements, HAND_LANDMARKS};
 pub use graph::{Edge, Graph, Node};
+pub use motion::{DepthMotion, Direction, PushConfig, PushDetector, SwipeConfig, SwipeDetector};
 pub use point_2f::Point2F;
+pub use point_3f::Point3F;
 pub use pointer::PointerTracker;
 pub use probability_vector::ProbabilityVector;
 pub use rectangle::Rectangle;
-pub use sign::{Feature, Sign};
-pub use sign_dictionary::SignDictionary;
+pub use sign::{Feature, Handedness, Sign, SignMode};
+pub use sign_dictionary::{Conflict, SignDictionary};
 pub use state::State;
+pub use trajectory::{StrokeTracker, Template, TrajectoryDictionary};
 pub use vec_2f::Vec2F;
 
 mod bit_string;
+mod calibration;
 mod circle;
+pub mod classifier;
 mod command;
+mod command_log;
+mod executor;
+mod feature_config;
 pub mod filter;
 mod graph;
+mod motion;
 mod point_2f;
+mod point_3f;
 pub mod pointer;
 mod probability_vector;
 mod rectangle;
 mod sign;
 mod sign_dictionary;
 pub mod state;
+mod trajectory;
 mod vec_2f;
@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumIter, EnumString, EnumVariantNames};
+
+use crate::common::{Point2F, Point3F};
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Display,
+    EnumIter,
+    EnumString,
+    EnumVariantNames,
+)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+// Motions of a landmark towards the camera and back
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Display,
+    EnumIter,
+    EnumString,
+    EnumVariantNames,
+)]
+pub enum DepthMotion {
+    Push,
+    Pull,
+    // A push and a pull in quick succession
+    Tap,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SwipeConfig {
+    // Fraction of the frame the hand has to travel
+    pub distance: f32,
+    // Fractions of the frame per second, on average
+    pub speed: f32,
+    // Milliseconds of motion taken into account
+    pub window: u64,
+    // Milliseconds after a swipe to move the hand back without swiping
+    pub rest: u64,
+}
+
+impl Default for SwipeConfig {
+    fn default() -> Self {
+        SwipeConfig {
+            distance: 0.2f32,
+            speed: 0.8f32,
+            window: 500,
+            rest: 400,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PushConfig {
+    // The landmark moved towards the camera, the index tip by default
+    pub landmark: usize,
+    // Palm widths the landmark has to travel, relative to the wrist
+    pub distance: f32,
+    // Milliseconds of motion taken into account
+    pub window: u64,
+    // Most milliseconds between the push and the pull of a tap
+    pub tap: u64,
+}
+
+impl Default for PushConfig {
+    fn default() -> Self {
+        PushConfig {
+            landmark: 8,
+            distance: 0.4f32,
+            window: 300,
+            tap: 500,
+        }
+    }
+}
+
+impl PushConfig {
+    // How far the landmark is in front of the wrist, in palm widths
+    pub fn depth(&self, landmarks: &[Point3F]) -> Option<f32> {
+        let landmark = landmarks.get(self.landmark)?;
+        let palm_width = Point2F::from(landmarks[5]).distance(&Point2F::from(landmarks[17]));
+
+        Some((landmarks[0].z - landmark.z) / palm_width.max(f32::EPSILON))
+    }
+}
+
+// Recognizes pushes and pulls from the depth of a landmark, the cursor barely moves with them
+#[derive(Default)]
+pub struct PushDetector {
+    samples: VecDeque<(f32, Instant)>,
+    last_push: Option<Instant>,
+}
+
+impl PushDetector {
+    // A pull right after a push is also a tap
+    pub fn track(
+        &mut self,
+        depth: Option<f32>,
+        time: Instant,
+        config: &PushConfig,
+    ) -> Vec<DepthMotion> {
+        let depth = match depth {
+            Some(depth) => depth,
+            None => {
+                self.samples.clear();
+                self.last_push = None;
+                return Vec::new();
+            }
+        };
+
+        self.samples.push_back((depth, time));
+        while matches!(self.samples.front(),
+            Some((_, sampled)) if time.saturating_duration_since(*sampled) > Duration::from_millis(config.window))
+        {
+            self.samples.pop_front();
+        }
+
+        let (nearest, farthest) = self
+            .samples
+            .iter()
+            .fold((f32::MIN, f32::MAX), |(nearest, farthest), (depth, _)| {
+                (nearest.max(*depth), farthest.min(*depth))
+            });
+        let motions = if depth - farthest >= config.distance {
+            self.last_push = Some(time);
+
+            vec![DepthMotion::Push]
+        } else if nearest - depth >= config.distance {
+            let tapped = matches!(self.last_push,
+                Some(last_push) if time.saturating_duration_since(last_push) <= Duration::from_millis(config.tap));
+            self.last_push = None;
+
+            if tapped {
+                vec![DepthMotion::Pull, DepthMotion::Tap]
+            } else {
+                vec![DepthMotion::Pull]
+            }
+        } else {
+            return Vec::new();
+        };
+
+        // Start over, a motion is only reported once
+        self.samples.clear();
+
+        motions
+    }
+}
+
+// Recognizes swipes from the hand center
+#[derive(Default)]
+pub struct SwipeDetector {
+    samples: VecDeque<(Point2F, Instant)>,
+    last_swipe: Option<Instant>,
+}
+
+impl SwipeDetector {
+    pub fn track(
+        &mut self,
+        center: Option<Point2F>,
+        time: Instant,
+        config: &SwipeConfig,
+    ) -> Option<Direction> {
+        let center = match center {
+            Some(center) => center,
+            None => {
+                self.samples.clear();
+                return None;
+            }
+        };
+
+        if matches!(self.last_swipe,
+            Some(last_swipe) if time.saturating_duration_since(last_swipe) < Duration::from_millis(config.rest))
+        {
+            return None;
+        }
+
+        self.samples.push_back((center, time));
+        while matches!(self.samples.front(),
+            Some((_, sampled)) if time.saturating_duration_since(*sampled) > Duration::from_millis(config.window))
+        {
+            self.samples.pop_front();
+        }
+
+        let (start, started) = *self.samples.front()?;
+        let elapsed = time.saturating_duration_since(started).as_secs_f32();
+        let (dx, dy) = (center.x - start.x, center.y - start.y);
+        let (distance, direction) = if dx.abs() >= dy.abs() {
+            (
+                dx.abs(),
+                if dx > 0f32 {
+                    Direction::Right
+                } else {
+                    Direction::Left
+                },
+            )
+        } else {
+            (
+                dy.abs(),
+                if dy > 0f32 {
+                    Direction::Down
+                } else {
+                    Direction::Up
+                },
+            )
+        };
+
+        if distance < config.distance || elapsed <= 0f32 || distance / elapsed < config.speed {
+            return None;
+        }
+
+        // Start over, a swipe is only reported once
+        self.samples.clear();
+        self.last_swipe = Some(time);
+
+        Some(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::common::motion::{
+        DepthMotion, Direction, PushConfig, PushDetector, SwipeConfig, SwipeDetector,
+    };
+    use crate::common::Point2F;
+
+    // Moves the hand by the given step every 33 ms
+    fn swipe(
+        detector: &mut SwipeDetector,
+        start: Instant,
+        step: (f32, f32),
+        frames: u32,
+    ) -> Vec<Direction> {
+        let config = SwipeConfig::default();
+
+        (0..frames)
+            .filter_map(|i| {
+                let center = Point2F::new(0.5f32 + step.0 * i as f32, 0.5f32 + step.1 * i as f32);
+                let time = start + Duration::from_millis(33 * i as u64);
+
+                detector.track(Some(center), time, &config)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fast_swipe() {
+        let mut detector = SwipeDetector::default();
+        let start = Instant::now();
+
+        assert_eq!(
+            swipe(&mut detector, start, (0.05f32, 0.01f32), 6),
+            vec![Direction::Right]
+        );
+        // Moving back right away is not a swipe
+        assert!(swipe(
+            &mut detector,
+            start + Duration::from_millis(200),
+            (-0.05f32, 0f32),
+            6
+        )
+        .is_empty());
+        assert_eq!(
+            swipe(
+                &mut detector,
+                start + Duration::from_secs(1),
+                (0f32, -0.05f32),
+                6
+            ),
+            vec![Direction::Up]
+        );
+    }
+
+    #[test]
+    fn slow_motion() {
+        let mut detector = SwipeDetector::default();
+
+        assert!(swipe(&mut detector, Instant::now(), (-0.005f32, 0f32), 60).is_empty());
+    }
+
+    #[test]
+    fn push_and_pull() {
+        let mut detector = PushDetector::default();
+        let config = PushConfig::default();
+        let start = Instant::now();
+        let mut track = |depth: f32, ms: u64| {
+            detector.track(Some(depth), start + Duration::from_millis(ms), &config)
+        };
+
+        assert!(track(0f32, 0).is_empty());
+        assert!(track(0.2f32, 33).is_empty());
+        assert_eq!(track(0.5f32, 66), vec![DepthMotion::Push]);
+        // Holding still in front
+        assert!(track(0.5f32, 300).is_empty());
+        assert_eq!(track(0f32, 333), vec![DepthMotion::Pull, DepthMotion::Tap]);
+
+        // Too slow for a tap
+        assert!(track(0f32, 1000).is_empty());
+        assert_eq!(track(0.5f32, 1033), vec![DepthMotion::Push]);
+        assert!(track(0.5f32, 2000).is_empty());
+        assert_eq!(track(0f32, 2033), vec![DepthMotion::Pull]);
+    }
+}
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::common::Vec2F;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[repr(C)]
 pub struct Point2F {
     pub x: f32,
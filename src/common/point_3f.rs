@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use crate::common::Point2F;
+
+// A landmark, z is the depth relative to the wrist on about the scale of x, smaller towards the camera
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[repr(C)]
+pub struct Point3F {
+    pub x: f32,
+    pub y: f32,
+    // Recordings made before the depth was kept have none
+    #[serde(default)]
+    pub z: f32,
+}
+
+impl Default for Point3F {
+    fn default() -> Self {
+        Self {
+            x: 0f32,
+            y: 0f32,
+            z: 0f32,
+        }
+    }
+}
+
+impl Point3F {
+    pub fn new(x: f32, y: f32, z: f32) -> Point3F {
+        Self { x, y, z }
+    }
+}
+
+// Projected on the frame
+impl From<Point3F> for Point2F {
+    fn from(point: Point3F) -> Self {
+        Point2F::new(point.x, point.y)
+    }
+}
@@ -2,12 +2,10 @@ use std::{thread, time::Duration};
 
 use image::{Rgb, RgbImage};
 use imageproc::drawing;
-use slint::{Image, Rgb8Pixel, SharedPixelBuffer};
 use tfc::{Context, Error, MouseContext, ScreenContext};
 
 use crate::common::filter::Wmaf32;
 use crate::common::{Point2F, Rectangle};
-use crate::mediapipe::Packet;
 
 // TODO: Move these to the config file
 const PRECISION_FACTOR: f32 = 2f32;
@@ -88,8 +86,8 @@ impl PointerTracker {
         &self.dynamic_virtual_box
     }
 
-    pub fn track(&mut self, packet: &Packet) -> Result<(), Error> {
-        if let Some(ref landmarks) = packet.landmarks {
+    pub fn track(&mut self, landmarks: Option<&[Point2F]>) -> Result<(), Error> {
+        if let Some(landmarks) = landmarks {
             let prev_x = *self.x;
             let prev_y = *self.y;
             let new_x = (landmarks[5].x + landmarks[17].x).abs() / 2f32;
@@ -143,7 +141,7 @@ impl PointerTracker {
         Ok(())
     }
 
-    pub fn annotate(frame: &mut RgbImage, mut dvb: Rectangle, center: (f32, f32)) -> Image {
+    pub fn annotate(frame: &mut RgbImage, mut dvb: Rectangle, center: (f32, f32)) {
         dvb.multiply(frame.width() as f32, frame.height() as f32);
         let center = (
             (center.0 * frame.width() as f32) as i32,
@@ -154,13 +152,6 @@ impl PointerTracker {
         let green = Rgb::from([0, 255, 0]);
         drawing::draw_hollow_rect_mut(frame, dvb.into(), green);
         drawing::draw_filled_circle_mut(frame, center, center_radius, green);
-
-        let buffer = SharedPixelBuffer::<Rgb8Pixel>::clone_from_slice(
-            frame.as_raw(),
-            frame.width(),
-            frame.height(),
-        );
-        Image::from_rgb8(buffer)
     }
 }
 
@@ -21,15 +21,25 @@ impl ProbabilityVector {
         self.probabilities.as_ref()
     }
 
-    pub fn adjust(&mut self, index: usize) {
-        let inverse = 1f32 - self.sensitivity;
+    // Soft evidence, from 0 to 1 for every index. Evidence adding up to less than 1
+    // leaves the rest spread evenly, so unclear poses drift towards no sign at all.
+    pub fn adjust_soft(&mut self, evidence: &[f32], sensitivity: f32) {
+        let total: f32 = evidence.iter().sum();
+        let scale = total.max(1f32);
+        let rest = (1f32 - total).max(0f32) / self.probabilities.len() as f32;
+        let inverse = 1f32 - sensitivity;
 
-        self.probabilities = self.probabilities.iter().map(|n| n * inverse).collect();
-        self.probabilities[index] += self.sensitivity;
+        self.probabilities = self
+            .probabilities
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let evidence = evidence.get(i).copied().unwrap_or_default() / scale;
+
+                n * inverse + sensitivity * (evidence + rest)
+            })
+            .collect();
 
-        // Adjust floating-point errors
-        // let sum: f32 = self.probabilities.iter().sum();
-        // self.probabilities[index] += 1f32 - sum;
         self.adjust_floating_point_errors();
     }
 
@@ -67,3 +77,25 @@ impl ProbabilityVector {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::common::ProbabilityVector;
+
+    #[test]
+    fn soft_evidence() {
+        let mut vector = ProbabilityVector::new(4, 0.5f32);
+
+        // The same as a single index
+        vector.adjust_soft(&[0f32, 1f32, 0f32, 0f32], 0.5f32);
+        assert!((vector.probabilities()[1] - 0.625f32).abs() < 1e-4);
+
+        // Weak evidence drifts towards even probabilities
+        for _ in 0..20 {
+            vector.adjust_soft(&[0.1f32, 0f32, 0.1f32, 0f32], 0.5f32);
+        }
+        let (_, max) = vector.max().unwrap();
+        assert!(max < 0.5f32);
+        assert!((vector.probabilities().iter().sum::<f32>() - 1f32).abs() < 1e-4);
+    }
+}
@@ -7,18 +7,61 @@ use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use strum::EnumCount;
 use strum::IntoEnumIterator;
-use strum_macros::{EnumCount as EnumCountMacro, EnumIter};
+use strum_macros::{Display, EnumCount as EnumCountMacro, EnumIter, EnumString, EnumVariantNames};
 
-use crate::common::{Circle, Point2F, Vec2F};
+use crate::common::{FeatureConfig, Point3F};
 
 use super::BitString;
 
+// Share of the captured samples a feature has to agree in to be kept
+const STABLE_RATIO: f32 = 0.9f32;
+
 #[allow(dead_code)]
 enum FeatureState {
     Exists(bool),
     Irrelevant,
 }
 
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Display,
+    EnumString,
+    EnumVariantNames,
+)]
+pub enum Handedness {
+    Left,
+    Right,
+}
+
+// How a sign is recognized
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Default,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Display,
+    EnumString,
+    EnumVariantNames,
+)]
+pub enum SignMode {
+    // From the required and irrelevant features
+    #[default]
+    Features,
+    // By the classifier, from the captured examples
+    Learned,
+}
+
 #[derive(Debug, Copy, Clone, Eq, Derivative, EnumCountMacro, EnumIter)]
 #[derivative(PartialEq, Hash)]
 pub enum Feature {
@@ -31,6 +74,17 @@ pub enum Feature {
     IndexMiddleSpread,
     MiddleRingSpread,
     RingPinkySpread,
+    // Stored signs index the features, new ones go last
+    ThumbClosed,
+    ThumbIndexPinch,
+    ThumbMiddleTouch,
+    ThumbRingTouch,
+    ThumbPinkyTouch,
+    IndexPointingUp,
+    IndexPointingDown,
+    IndexPointingSideways,
+    PalmFacing,
+    IndexForward,
 }
 
 impl Display for Feature {
@@ -45,6 +99,16 @@ impl Display for Feature {
             Feature::IndexMiddleSpread => "Indx Mdl",
             Feature::MiddleRingSpread => "Mdl Rng",
             Feature::RingPinkySpread => "Rng Pnk",
+            Feature::ThumbClosed => "Thumb",
+            Feature::ThumbIndexPinch => "Pinch",
+            Feature::ThumbMiddleTouch => "Thmb Mdl",
+            Feature::ThumbRingTouch => "Thmb Rng",
+            Feature::ThumbPinkyTouch => "Thmb Pnk",
+            Feature::IndexPointingUp => "Up",
+            Feature::IndexPointingDown => "Down",
+            Feature::IndexPointingSideways => "Side",
+            Feature::PalmFacing => "Palm",
+            Feature::IndexForward => "Fwd",
         };
 
         write!(f, "{}", feature)
@@ -55,6 +119,23 @@ impl Display for Feature {
 pub struct Sign {
     required_attributes: BitString,
     irrelevant_attributes: BitString,
+
+    // Override the global probability threshold and switching smoothness
+    #[serde(default)]
+    pub threshold: Option<f32>,
+    #[serde(default)]
+    pub smoothness: Option<f32>,
+    // Overlapping signs with a higher priority win, unset is 0
+    #[serde(default)]
+    pub priority: Option<i32>,
+    // Only recognized on this hand, on either if none
+    #[serde(default)]
+    pub hand: Option<Handedness>,
+    #[serde(default)]
+    pub mode: SignMode,
+    // Normalized landmarks of the captured frames, see classifier::normalize
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Vec<f32>>,
 }
 
 impl Sign {
@@ -66,6 +147,107 @@ impl Sign {
         &self.irrelevant_attributes
     }
 
+    // Keeps the features that were stable across the samples, the others become irrelevant
+    pub fn from_samples(samples: &[Sign]) -> Sign {
+        let mut sign = Sign::default();
+        let total = samples.len() as f32;
+
+        for i in 0..Feature::COUNT {
+            let relevant = samples
+                .iter()
+                .filter(|sample| !sample.irrelevant_attributes.get(i));
+            let (present, absent) = relevant.fold((0f32, 0f32), |(present, absent), sample| {
+                if sample.required_attributes.get(i) {
+                    (present + 1f32, absent)
+                } else {
+                    (present, absent + 1f32)
+                }
+            });
+
+            if total > 0f32 && present / total >= STABLE_RATIO {
+                sign.set_feature(i, false, true);
+            } else if total > 0f32 && absent / total >= STABLE_RATIO {
+                sign.set_feature(i, false, false);
+            }
+        }
+
+        sign
+    }
+
+    pub fn recognized_on(&self, handedness: Handedness) -> bool {
+        self.hand.is_none() || self.hand == Some(handedness)
+    }
+
+    // A hand pose matching both signs, if any. Learned signs have no features to compare.
+    pub fn overlap(&self, other: &Sign) -> Option<Sign> {
+        let same_hand = match (self.hand, other.hand) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        let learned = self.mode == SignMode::Learned || other.mode == SignMode::Learned;
+        if !same_hand || learned || self != other {
+            return None;
+        }
+
+        let relevant =
+            |sign: &Sign| sign.required_attributes.bits() & !sign.irrelevant_attributes.bits();
+
+        Some(Sign {
+            required_attributes: BitString::new(relevant(self) | relevant(other), Feature::COUNT),
+            irrelevant_attributes: BitString::new(0, Feature::COUNT),
+            ..Sign::default()
+        })
+    }
+
+    // Whether both signs look at the same features the same way, so they always fit a hand equally well
+    pub fn same_features(&self, other: &Sign) -> bool {
+        self.irrelevant_attributes.bits() == other.irrelevant_attributes.bits() && self == other
+    }
+
+    // How well the scores of a hand fit the relevant features, from 0 to 1, see FeatureConfig::scores.
+    // The geometric mean, so signs with more features aren't less likely.
+    pub fn likelihood(&self, scores: &[f32]) -> f32 {
+        let (sum, count) = (0..Feature::COUNT)
+            .filter(|i| !self.irrelevant_attributes.get(*i))
+            .fold((0f32, 0f32), |(sum, count), i| {
+                let score = scores.get(i).copied().unwrap_or(0.5f32);
+                let fit = if self.required_attributes.get(i) {
+                    score
+                } else {
+                    1f32 - score
+                };
+
+                (sum + fit.max(f32::MIN_POSITIVE).ln(), count + 1f32)
+            });
+
+        if count > 0f32 {
+            (sum / count).exp()
+        } else {
+            1f32
+        }
+    }
+
+    // The features the sign requires
+    pub fn features(&self) -> Vec<Feature> {
+        Feature::iter()
+            .enumerate()
+            .filter(|(i, _)| {
+                self.required_attributes.get(*i) && !self.irrelevant_attributes.get(*i)
+            })
+            .map(|(_, feature)| feature)
+            .collect()
+    }
+
+    // Features added since the sign was stored are irrelevant to it
+    pub fn add_missing_features(&mut self) {
+        for i in self.irrelevant_attributes.capacity()..Feature::COUNT {
+            self.set_feature(i, true, false);
+        }
+
+        self.required_attributes.set_capacity(Feature::COUNT);
+        self.irrelevant_attributes.set_capacity(Feature::COUNT);
+    }
+
     pub fn set_feature(&mut self, feature_index: usize, irrelevant: bool, required: bool) {
         if irrelevant {
             self.irrelevant_attributes.set(feature_index);
@@ -84,7 +266,13 @@ impl Default for Sign {
     fn default() -> Self {
         Self {
             required_attributes: BitString::new(0, Feature::COUNT),
-            irrelevant_attributes: BitString::new(usize::MAX, Feature::COUNT),
+            irrelevant_attributes: BitString::new(u64::MAX, Feature::COUNT),
+            threshold: None,
+            smoothness: None,
+            priority: None,
+            hand: None,
+            mode: SignMode::default(),
+            examples: Vec::new(),
         }
     }
 }
@@ -122,66 +310,10 @@ impl fmt::Display for Sign {
     }
 }
 
-impl From<&[Point2F]> for Sign {
-    fn from(landmarks: &[Point2F]) -> Self {
-        let mut features = HashMap::new();
-        let palm_circle = Circle::from(vec![landmarks[0], landmarks[5], landmarks[17]].as_ref());
-
-        let hand_angle = Vec2F::from((landmarks[0], landmarks[17]))
-            .angle(&Vec2F { x: 0f32, y: 1f32 })
-            .to_degrees();
-
-        let thumb_index_angle = Vec2F::from((landmarks[0], landmarks[4]))
-            .angle(&Vec2F::from((landmarks[0], landmarks[5])))
-            .to_degrees();
-        let index_middle_angle = Vec2F::from((landmarks[5], landmarks[8]))
-            .angle(&Vec2F::from((landmarks[9], landmarks[12])))
-            .to_degrees();
-        let middle_ring_angle = Vec2F::from((landmarks[9], landmarks[12]))
-            .angle(&Vec2F::from((landmarks[13], landmarks[16])))
-            .to_degrees();
-        let ring_pinky_angle = Vec2F::from((landmarks[13], landmarks[16]))
-            .angle(&Vec2F::from((landmarks[17], landmarks[20])))
-            .to_degrees();
-
-        features.insert(
-            Feature::IndexClosed,
-            FeatureState::Exists(palm_circle.contains(&landmarks[8])),
-        );
-        features.insert(
-            Feature::MiddleClosed,
-            FeatureState::Exists(palm_circle.contains(&landmarks[12])),
-        );
-        features.insert(
-            Feature::RingClosed,
-            FeatureState::Exists(palm_circle.contains(&landmarks[16])),
-        );
-        features.insert(
-            Feature::PinkyClosed,
-            FeatureState::Exists(palm_circle.contains(&landmarks[20])),
-        );
-        features.insert(
-            Feature::HandRotated,
-            FeatureState::Exists(hand_angle < 140f32),
-        );
-        features.insert(
-            Feature::ThumbIndexSpread,
-            FeatureState::Exists(thumb_index_angle > 10f32),
-        );
-        features.insert(
-            Feature::IndexMiddleSpread,
-            FeatureState::Exists(index_middle_angle > 10f32),
-        );
-        features.insert(
-            Feature::MiddleRingSpread,
-            FeatureState::Exists(middle_ring_angle > 10f32),
-        );
-        features.insert(
-            Feature::RingPinkySpread,
-            FeatureState::Exists(ring_pinky_angle > 10f32),
-        );
-
-        features.into()
+// With the default thresholds, see FeatureConfig
+impl From<&[Point3F]> for Sign {
+    fn from(landmarks: &[Point3F]) -> Self {
+        FeatureConfig::default().sign(landmarks, Handedness::Right, None)
     }
 }
 
@@ -209,3 +341,82 @@ impl From<&HashMap<Feature, FeatureState>> for Sign {
         sign
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use strum::EnumCount;
+
+    use crate::common::sign::{Feature, FeatureState, Sign};
+
+    fn sample(index: bool, middle: bool) -> Sign {
+        let mut features = HashMap::new();
+
+        features.insert(Feature::IndexClosed, FeatureState::Exists(index));
+        features.insert(Feature::MiddleClosed, FeatureState::Exists(middle));
+        features.insert(Feature::RingClosed, FeatureState::Exists(false));
+
+        features.into()
+    }
+
+    #[test]
+    fn stable_features_from_samples() {
+        let samples: Vec<Sign> = (0..10).map(|i| sample(true, i % 2 == 0)).collect();
+        let sign = Sign::from_samples(&samples);
+
+        let index = Feature::IndexClosed as usize;
+        let middle = Feature::MiddleClosed as usize;
+        let ring = Feature::RingClosed as usize;
+        assert!(!sign.irrelevant_attributes().get(index) && sign.required_attributes().get(index));
+        assert!(sign.irrelevant_attributes().get(middle));
+        assert!(!sign.irrelevant_attributes().get(ring) && !sign.required_attributes().get(ring));
+        assert!(sign
+            .irrelevant_attributes()
+            .get(Feature::PinkyClosed as usize));
+    }
+
+    #[test]
+    fn stored_signs_get_the_new_features() {
+        let yaml = "required_attributes: {bits: 2, capacity: 9}\nirrelevant_attributes: {bits: 0, capacity: 9}";
+        let mut sign: Sign = serde_yaml::from_str(yaml).unwrap();
+
+        sign.add_missing_features();
+        assert_eq!(
+            Vec::<bool>::from(sign.irrelevant_attributes()).len(),
+            Feature::COUNT
+        );
+        assert!(sign
+            .required_attributes()
+            .get(Feature::IndexClosed as usize));
+        assert!(!sign
+            .irrelevant_attributes()
+            .get(Feature::MiddleClosed as usize));
+        assert!(sign
+            .irrelevant_attributes()
+            .get(Feature::PalmFacing as usize));
+        assert!(sign
+            .irrelevant_attributes()
+            .get(Feature::IndexForward as usize));
+    }
+
+    #[test]
+    fn overlapping_signs() {
+        let mut fist = sample(true, true);
+        fist.set_feature(Feature::PinkyClosed as usize, false, true);
+        let mut closed_index = Sign::default();
+        closed_index.set_feature(Feature::IndexClosed as usize, false, true);
+
+        let pose = fist.overlap(&closed_index).unwrap();
+        assert_eq!(
+            pose.features(),
+            vec![
+                Feature::IndexClosed,
+                Feature::MiddleClosed,
+                Feature::PinkyClosed
+            ]
+        );
+        assert!(pose == fist && pose == closed_index);
+        assert!(sample(true, true).overlap(&sample(true, false)).is_none());
+    }
+}
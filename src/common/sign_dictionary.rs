@@ -1,10 +1,15 @@
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
+use std::fmt;
 
+use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
-use crate::common::Sign;
+use crate::common::{Classifier, Feature, Handedness, Sign, SignMode};
 
 const NEW_SIGN_NAME: &str = "New Sign ";
+// How much less likely than the most likely sign an overlapping one can be and still win by priority
+const PRIORITY_MARGIN: f32 = 0.1f32;
 
 #[derive(Serialize, Deserialize)]
 pub struct SignDictionary {
@@ -40,18 +45,118 @@ pub struct IndexedSign<'a> {
 }
 
 impl SignDictionary {
-    pub fn find_similar(&self, sign: &Sign) -> Option<IndexedSign> {
-        for (index, (name, other)) in self.signs.iter().enumerate() {
-            if other == sign {
-                return Some(IndexedSign {
-                    index,
-                    name,
-                    sign: other,
-                });
+    // How well a hand fits each sign, by index. From the feature scores, or the normalized
+    // landmarks for the learned signs.
+    pub fn likelihoods(
+        &self,
+        scores: &[f32],
+        landmarks: &[f32],
+        handedness: Handedness,
+        classifier: &Classifier,
+    ) -> Vec<f32> {
+        let signs: Vec<&Sign> = self.signs.values().collect();
+        let learned = classifier.likelihoods(&signs, landmarks);
+
+        signs
+            .iter()
+            .zip(learned)
+            .map(|(sign, learned)| {
+                if !sign.recognized_on(handedness) {
+                    0f32
+                } else if sign.mode == SignMode::Learned {
+                    learned
+                } else {
+                    sign.likelihood(scores)
+                }
+            })
+            .collect()
+    }
+
+    // The most likely sign, unless a sign overlapping it is about as likely and has a higher
+    // priority, see conflicts
+    pub fn find_likely(&self, likelihoods: &[f32], min_likelihood: f32) -> Option<IndexedSign<'_>> {
+        let candidates: Vec<(usize, (&String, &Sign), f32)> = self
+            .signs
+            .iter()
+            .zip(likelihoods)
+            .enumerate()
+            .filter(|(_, (_, likelihood))| **likelihood >= min_likelihood)
+            .map(|(index, (sign, likelihood))| (index, sign, *likelihood))
+            .collect();
+        let (most_likely, (_, best), best_likelihood) = *candidates
+            .iter()
+            .max_by_key(|(_, _, likelihood)| OrderedFloat(*likelihood))?;
+
+        candidates
+            .into_iter()
+            .filter(|(index, (_, sign), likelihood)| {
+                *index == most_likely
+                    || (*likelihood >= best_likelihood - PRIORITY_MARGIN
+                        && sign.overlap(best).is_some())
+            })
+            .max_by_key(|(_, (_, sign), likelihood)| (priority(sign), OrderedFloat(*likelihood)))
+            .map(|(index, (name, sign), _)| IndexedSign { index, name, sign })
+    }
+
+    // Soft evidence for the probability vector. The likely sign keeps its likelihood and the others
+    // share the rest, so signs overlapping it don't split the probability.
+    pub fn evidence(&self, likelihoods: &[f32], min_likelihood: f32) -> Vec<f32> {
+        let likely = match self.find_likely(likelihoods, min_likelihood) {
+            Some(likely) => likely.index,
+            None => return likelihoods.to_vec(),
+        };
+        let rest = 1f32 - likelihoods[likely];
+        let others: f32 = likelihoods.iter().sum::<f32>() - likelihoods[likely];
+
+        likelihoods
+            .iter()
+            .enumerate()
+            .map(|(i, likelihood)| {
+                if i == likely {
+                    *likelihood
+                } else if others > 0f32 {
+                    rest * likelihood / others
+                } else {
+                    0f32
+                }
+            })
+            .collect()
+    }
+
+    // Every pair of signs matching the same hand pose, the only ones priorities decide between
+    pub fn conflicts(&self) -> Vec<Conflict<'_>> {
+        let mut conflicts = Vec::new();
+
+        for (i, (first, a)) in self.signs.iter().enumerate() {
+            for (second, b) in self.signs.iter().skip(i + 1) {
+                if let Some(example) = a.overlap(b) {
+                    let winner = match priority(a).cmp(&priority(b)) {
+                        Ordering::Greater => Some(first),
+                        Ordering::Less => Some(second),
+                        Ordering::Equal => None,
+                    };
+
+                    conflicts.push(Conflict {
+                        first,
+                        second,
+                        winner,
+                        identical: a.same_features(b),
+                        example,
+                    });
+                }
             }
         }
 
-        None
+        conflicts
+    }
+
+    // Learned signs without examples, never recognized until they are captured
+    pub fn untrained(&self) -> Vec<&String> {
+        self.signs
+            .iter()
+            .filter(|(_, sign)| sign.mode == SignMode::Learned && sign.examples.is_empty())
+            .map(|(name, _)| name)
+            .collect()
     }
 
     pub fn get_by_index(&self, index: usize) -> Option<(&String, &Sign)> {
@@ -65,6 +170,57 @@ impl SignDictionary {
     }
 }
 
+fn priority(sign: &Sign) -> i32 {
+    sign.priority.unwrap_or_default()
+}
+
+// Two signs matching the same hand pose, the winner is recognized in their place while both fit
+// about as well
+pub struct Conflict<'a> {
+    pub first: &'a String,
+    pub second: &'a String,
+    // The sign with the higher priority, whichever fits the hand better wins otherwise
+    pub winner: Option<&'a String>,
+    // Both signs always fit equally well, so without a winner neither can be told apart
+    pub identical: bool,
+    // A pose matching both signs
+    pub example: Sign,
+}
+
+impl fmt::Display for Conflict<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let features: Vec<String> = self
+            .example
+            .features()
+            .iter()
+            .map(Feature::to_string)
+            .collect();
+        let pose = if features.is_empty() {
+            String::from("no features")
+        } else {
+            features.join(", ")
+        };
+
+        match self.winner {
+            Some(winner) => write!(
+                f,
+                "{} and {} both match {}, {} wins by priority",
+                self.first, self.second, pose, winner
+            ),
+            None if self.identical => write!(
+                f,
+                "{} and {} match the same poses, give one a higher priority",
+                self.first, self.second
+            ),
+            None => write!(
+                f,
+                "{} and {} both match {}, the closer one wins",
+                self.first, self.second, pose
+            ),
+        }
+    }
+}
+
 impl From<BTreeMap<String, Sign>> for SignDictionary {
     fn from(map: BTreeMap<String, Sign>) -> Self {
         SignDictionary { signs: map }
@@ -76,3 +232,169 @@ impl From<SignDictionary> for BTreeMap<String, Sign> {
         dict.signs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use ordered_float::OrderedFloat;
+    use strum::EnumCount;
+
+    use crate::common::{Classifier, Feature, Handedness, Sign, SignDictionary};
+
+    fn sign(features: &[Feature]) -> Sign {
+        let mut sign = Sign::default();
+
+        for feature in features {
+            sign.set_feature(*feature as usize, false, true);
+        }
+
+        sign
+    }
+
+    // The sign recognized from the scores, which the probability vector follows too
+    fn likely(dictionary: &SignDictionary, scores: &[f32], handedness: Handedness) -> String {
+        let likelihoods = dictionary.likelihoods(scores, &[], handedness, &Classifier::default());
+        let likely = dictionary.find_likely(&likelihoods, 0.6f32).unwrap();
+        let evidence = dictionary.evidence(&likelihoods, 0.6f32);
+        let (strongest, _) = evidence
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, evidence)| OrderedFloat(**evidence))
+            .unwrap();
+
+        assert_eq!(strongest, likely.index);
+        likely.name.clone()
+    }
+
+    #[test]
+    fn report_conflicts() {
+        let mut signs = BTreeMap::new();
+        signs.insert(String::from("A"), sign(&[Feature::IndexClosed]));
+        signs.insert(
+            String::from("B"),
+            sign(&[Feature::IndexClosed, Feature::MiddleClosed]),
+        );
+        let mut c = sign(&[]);
+        c.set_feature(Feature::IndexClosed as usize, false, false);
+        signs.insert(String::from("C"), c);
+        let mut dictionary = SignDictionary::from(signs);
+
+        let conflicts = dictionary.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "A and B both match Index, Middle, the closer one wins"
+        );
+
+        let mut pose = vec![0.5f32; Feature::COUNT];
+        pose[Feature::IndexClosed as usize] = 0.95f32;
+        pose[Feature::MiddleClosed as usize] = 0.9f32;
+        let right = Handedness::Right;
+        assert_eq!(likely(&dictionary, &pose, right), "A");
+
+        dictionary.signs_mut().get_mut("B").unwrap().priority = Some(1);
+        assert_eq!(
+            dictionary.conflicts()[0].to_string(),
+            "A and B both match Index, Middle, B wins by priority"
+        );
+        assert_eq!(likely(&dictionary, &pose, right), "B");
+
+        // Signs of different hands never match the same hand
+        dictionary.signs_mut().get_mut("A").unwrap().hand = Some(Handedness::Left);
+        dictionary.signs_mut().get_mut("B").unwrap().hand = Some(right);
+        assert!(dictionary.conflicts().is_empty());
+        assert_eq!(likely(&dictionary, &pose, Handedness::Left), "A");
+
+        let mut signs = BTreeMap::new();
+        signs.insert(String::from("A"), sign(&[Feature::IndexClosed]));
+        signs.insert(String::from("D"), sign(&[Feature::IndexClosed]));
+        let dictionary = SignDictionary::from(signs);
+
+        let conflicts = dictionary.conflicts();
+        assert!(conflicts[0].identical && conflicts[0].winner.is_none());
+        assert_eq!(
+            conflicts[0].to_string(),
+            "A and D match the same poses, give one a higher priority"
+        );
+    }
+
+    #[test]
+    fn likely_signs() {
+        let mut signs = BTreeMap::new();
+        signs.insert(String::from("INDEX"), sign(&[Feature::IndexClosed]));
+        signs.insert(
+            String::from("TWO"),
+            sign(&[Feature::IndexClosed, Feature::MiddleClosed]),
+        );
+        let mut left = sign(&[Feature::PinkyClosed]);
+        left.hand = Some(Handedness::Left);
+        signs.insert(String::from("LEFT"), left);
+        let mut dictionary = SignDictionary::from(signs);
+
+        // The middle finger is unclear, the pinky clearly closed
+        let mut scores = vec![0.5f32; Feature::COUNT];
+        scores[Feature::IndexClosed as usize] = 0.95f32;
+        scores[Feature::MiddleClosed as usize] = 0.4f32;
+        scores[Feature::PinkyClosed as usize] = 0.99f32;
+
+        let likelihoods =
+            dictionary.likelihoods(&scores, &[], Handedness::Right, &Classifier::default());
+        assert_eq!(likelihoods[1], 0f32);
+        assert!(likelihoods[0] > likelihoods[2]);
+        assert_eq!(
+            dictionary.find_likely(&likelihoods, 0.6f32).unwrap().name,
+            "INDEX"
+        );
+        assert!(dictionary.find_likely(&likelihoods, 0.99f32).is_none());
+
+        // A higher priority does not beat a much better fit
+        dictionary.signs_mut().get_mut("TWO").unwrap().priority = Some(1);
+        assert!(likelihoods[2] >= 0.6f32);
+        assert_eq!(
+            dictionary.find_likely(&likelihoods, 0.6f32).unwrap().name,
+            "INDEX"
+        );
+
+        // Only once both fit about as well
+        let mut closed = scores.clone();
+        closed[Feature::MiddleClosed as usize] = 0.9f32;
+        let closed_likelihoods =
+            dictionary.likelihoods(&closed, &[], Handedness::Right, &Classifier::default());
+        assert!(closed_likelihoods[0] > closed_likelihoods[2]);
+        assert_eq!(
+            dictionary
+                .find_likely(&closed_likelihoods, 0.6f32)
+                .unwrap()
+                .name,
+            "TWO"
+        );
+
+        let evidence = dictionary.evidence(&likelihoods, 0.6f32);
+        assert_eq!(evidence[0], likelihoods[0]);
+        assert!((evidence.iter().sum::<f32>() - 1f32).abs() < 1e-4);
+    }
+
+    #[test]
+    fn priority_between_overlapping_signs_only() {
+        let mut signs = BTreeMap::new();
+        signs.insert(String::from("INDEX"), sign(&[Feature::IndexClosed]));
+        let mut open = sign(&[]);
+        open.set_feature(Feature::IndexClosed as usize, false, false);
+        open.priority = Some(1);
+        signs.insert(String::from("OPEN"), open);
+        let dictionary = SignDictionary::from(signs);
+
+        // The index finger is unclear, both fit about as well but never the same hand
+        let mut scores = vec![0.5f32; Feature::COUNT];
+        scores[Feature::IndexClosed as usize] = 0.52f32;
+        let likelihoods =
+            dictionary.likelihoods(&scores, &[], Handedness::Right, &Classifier::default());
+
+        assert!(dictionary.conflicts().is_empty());
+        assert_eq!(
+            dictionary.find_likely(&likelihoods, 0.4f32).unwrap().name,
+            "INDEX"
+        );
+    }
+}
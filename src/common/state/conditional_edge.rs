@@ -1,18 +1,54 @@
 use std::hash::Hash;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumDiscriminants, EnumString, EnumVariantNames};
 
 use crate::common::Edge;
 
+// How long the trigger has to keep matching before the edge fires
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, EnumDiscriminants)]
+#[strum_discriminants(derive(Display, EnumString, EnumVariantNames))]
+pub enum Hold {
+    // Milliseconds
+    Duration(u64),
+    // Consecutive processed frames
+    Frames(u32),
+}
+
+impl Hold {
+    // Whether a trigger matching since `since`, for `frames` frames, was held long enough
+    pub fn satisfied(&self, since: Instant, frames: u32, now: Instant) -> bool {
+        match *self {
+            Hold::Duration(ms) => now.saturating_duration_since(since) >= Duration::from_millis(ms),
+            Hold::Frames(count) => frames >= count,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ConditionalEdge<I, J> {
     next: I,
     pub trigger: J,
+    #[serde(default)]
+    pub hold: Option<Hold>,
+    // Milliseconds before the edge can fire again
+    #[serde(default)]
+    pub cooldown: Option<u64>,
+    // Probability the sign has to pass, instead of the sign's or the global one
+    #[serde(default)]
+    pub threshold: Option<f32>,
 }
 
 impl<I, J> ConditionalEdge<I, J> {
     pub fn new(next: I, trigger: J) -> Self {
-        ConditionalEdge { next, trigger }
+        ConditionalEdge {
+            next,
+            trigger,
+            hold: None,
+            cooldown: None,
+            threshold: None,
+        }
     }
 
     pub fn trigger(&self) -> &J {
@@ -25,3 +61,31 @@ impl<I: Eq + Hash + Copy, J: Eq + Hash> Edge<I> for ConditionalEdge<I, J> {
         self.next
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::common::state::{ConditionalEdge, Hold, Trigger};
+
+    #[test]
+    fn hold_defaults_to_none() {
+        let edge: ConditionalEdge<i32, Option<Trigger>> =
+            serde_yaml::from_str("next: 1\ntrigger: FIST").unwrap();
+
+        assert_eq!(edge.hold, None);
+        assert_eq!(edge.cooldown, None);
+        assert_eq!(edge.threshold, None);
+    }
+
+    #[test]
+    fn hold_satisfied() {
+        let since = Instant::now();
+        let later = since + Duration::from_millis(300);
+
+        assert!(!Hold::Duration(300).satisfied(since, 1, since));
+        assert!(Hold::Duration(300).satisfied(since, 1, later));
+        assert!(!Hold::Frames(5).satisfied(since, 4, later));
+        assert!(Hold::Frames(5).satisfied(since, 5, since));
+    }
+}
@@ -4,26 +4,32 @@ use std::hash::Hash;
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumIter, EnumString};
 
-pub use conditional_edge::ConditionalEdge;
-pub use state_machine::StateMachine;
+pub use conditional_edge::{ConditionalEdge, Hold, HoldDiscriminants};
+pub use state_machine::{Observation, StateMachine};
+pub use trigger::{Trigger, TriggerDiscriminants};
 
 use crate::common::graph::Node;
 use crate::common::{Axis, Command, ScrollCommand};
 
 mod conditional_edge;
 mod state_machine;
+mod trigger;
 
 #[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Display, EnumIter, EnumString)]
 pub enum StateType {
     Basic,
     Pointing,
     Scrolling,
+    // Never entered, its edges apply to every state sharing one of its tags, or all if it has none
+    Wildcard,
 }
 
 #[derive(Serialize, Deserialize, EnumString, Hash, PartialEq, Eq, Display)]
 pub enum StateEvent {
     OnEnter,
     OnExit,
+    // Leaving through a time based edge, right before OnExit
+    OnTimeout,
     OnScrollX,
     OnScrollY,
 }
@@ -38,6 +44,8 @@ pub struct State<I: Eq + Hash> {
     // a more fabulous enum for the state type
     r#type: StateType,
     events: HashMap<StateEvent, Command>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 
     // If you think that these should be stored somewhere else, meh, you are right.
     pub x: f32,
@@ -46,18 +54,39 @@ pub struct State<I: Eq + Hash> {
 
 impl<I: Eq + Hash> State<I> {
     pub fn new(index: I) -> State<I> {
-        let mut events = HashMap::new();
-
-        events.insert(StateEvent::OnEnter, Command::Disabled);
-        events.insert(StateEvent::OnExit, Command::Disabled);
-
         State {
             index,
             name: String::from("New "),
             x: 0f32,
             y: 0f32,
             r#type: StateType::Basic,
-            events,
+            events: Self::default_events(),
+            tags: Vec::new(),
+        }
+    }
+
+    // Whether the edges of this wildcard state apply while in the given state
+    pub fn covers(&self, state: &State<I>) -> bool {
+        self.r#type == StateType::Wildcard
+            && state.r#type != StateType::Wildcard
+            && (self.tags.is_empty() || self.tags.iter().any(|tag| state.tags.contains(tag)))
+    }
+
+    // Events every state has, regardless of its type
+    fn default_events() -> HashMap<StateEvent, Command> {
+        let mut events = HashMap::new();
+
+        events.insert(StateEvent::OnEnter, Command::Disabled);
+        events.insert(StateEvent::OnExit, Command::Disabled);
+        events.insert(StateEvent::OnTimeout, Command::Disabled);
+
+        events
+    }
+
+    // States saved by older versions lack the events added since
+    pub fn add_missing_events(&mut self) {
+        for (event, command) in Self::default_events() {
+            self.events.entry(event).or_insert(command);
         }
     }
 
@@ -94,6 +123,7 @@ impl<I: Eq + Hash> State<I> {
                 self.events.remove(&StateEvent::OnScrollX);
                 self.events.remove(&StateEvent::OnScrollY);
             }
+            StateType::Wildcard => (),
         }
 
         // Augmentation
@@ -119,6 +149,7 @@ impl<I: Eq + Hash> State<I> {
                     }),
                 );
             }
+            StateType::Wildcard => (),
         }
     }
 }
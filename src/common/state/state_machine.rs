@@ -1,19 +1,91 @@
-use std::fmt::Display;
+use std::collections::{HashMap, VecDeque};
 use std::hash::Hash;
+use std::time::{Duration, Instant};
 use std::{thread, time};
 
-use crate::common::state::StateEvent;
-use crate::common::{Edge, Graph, PointerTracker};
+use crate::common::state::{StateEvent, StateType};
+use crate::common::{
+    CommandExecutor, DepthMotion, Direction, Edge, Graph, HandDistance, Handedness, Node,
+    PointerTracker,
+};
 
-use super::{ConditionalEdge, State};
+use super::{ConditionalEdge, State, Trigger};
+
+// Enough for any sensible sign sequence, with some unrecognized signs in between
+const MAX_SIGN_HISTORY: usize = 32;
+
+// What the core saw in the current frame
+pub struct Observation<'a> {
+    // The most probable sign, if it is also the one seen in the current frame
+    pub sign: Option<&'a str>,
+    pub probability: f32,
+    // The sign's own threshold, or the global one
+    pub threshold: f32,
+    // The sign recognized on each hand, smoothed and thresholded like the overall one
+    pub hand_signs: HashMap<Handedness, &'a str>,
+    // How far apart the hands are, once both are seen
+    pub hand_distance: Option<HandDistance>,
+    pub swipe: Option<Direction>,
+    // Pushes, pulls and taps finished in this frame
+    pub depth_motions: Vec<DepthMotion>,
+    // The recognized trajectory template, once a stroke ended
+    pub trajectory: Option<&'a str>,
+    pub hand_visible: bool,
+    pub time: Instant,
+}
+
+impl<'a> Observation<'a> {
+    // The sign, once its probability passed the given threshold or the sign's one
+    pub fn recognized(&self, threshold: Option<f32>) -> Option<&'a str> {
+        if self.probability > threshold.unwrap_or(self.threshold) {
+            self.sign
+        } else {
+            None
+        }
+    }
+}
+
+// Since when an edge's trigger keeps matching
+struct Dwell {
+    since: Instant,
+    frames: u32,
+}
+
+// A sign seen in consecutive frames, recognized or not, so every edge can apply its own threshold
+struct SeenSign {
+    sign: String,
+    since: Instant,
+    // The highest probability while it was seen
+    peak: f32,
+    // The sign's own threshold, or the global one
+    threshold: f32,
+}
 
 pub struct StateMachine<I: Eq + Hash> {
     current_state: I,
+    executor: CommandExecutor,
+
+    entered_at: Instant,
+    hand_last_seen: Instant,
+
+    // Edges by their source and target states
+    dwelling: HashMap<(I, I), Dwell>,
+    last_fired: HashMap<(I, I), Instant>,
+    // Signs seen since entering the current state, without repetitions
+    sign_history: VecDeque<SeenSign>,
 }
 
 impl<I: Eq + Hash + Copy> StateMachine<I> {
-    pub fn new(current_state: I) -> Self {
-        let machine = StateMachine { current_state };
+    pub fn new(current_state: I, executor: CommandExecutor) -> Self {
+        let machine = StateMachine {
+            current_state,
+            executor,
+            entered_at: Instant::now(),
+            hand_last_seen: Instant::now(),
+            dwelling: HashMap::new(),
+            last_fired: HashMap::new(),
+            sign_history: VecDeque::new(),
+        };
         thread::sleep(time::Duration::from_millis(10)); // Needed to create a new context
 
         machine
@@ -23,60 +95,569 @@ impl<I: Eq + Hash + Copy> StateMachine<I> {
         &self.current_state
     }
 
-    pub fn process<J: Eq + Hash + Display>(
+    pub fn executor_mut(&mut self) -> &mut CommandExecutor {
+        &mut self.executor
+    }
+
+    pub fn process(
         &mut self,
-        state_graph: &Graph<I, State<I>, ConditionalEdge<I, Option<J>>>,
-        trigger: &J,
+        state_graph: &Graph<I, State<I>, ConditionalEdge<I, Option<Trigger>>>,
+        observation: &Observation,
         pointer: &mut PointerTracker,
     ) -> bool {
-        let edges = state_graph
+        match self.advance(state_graph, observation) {
+            Some((previous_state, timed)) => {
+                if timed {
+                    self.try_execute(previous_state, &StateEvent::OnTimeout, state_graph, pointer);
+                }
+                self.try_execute(previous_state, &StateEvent::OnExit, state_graph, pointer);
+                self.try_execute(
+                    self.current_state,
+                    &StateEvent::OnEnter,
+                    state_graph,
+                    pointer,
+                );
+
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Follows the edge that fired, if any, and returns the state it left and whether the
+    // trigger was time based, the events are left to the caller
+    fn advance(
+        &mut self,
+        state_graph: &Graph<I, State<I>, ConditionalEdge<I, Option<Trigger>>>,
+        observation: &Observation,
+    ) -> Option<(I, bool)> {
+        if observation.hand_visible {
+            self.hand_last_seen = observation.time;
+        }
+        if let Some(sign) = observation.sign {
+            self.remember_sign(sign, observation);
+        }
+
+        let current_state = state_graph
+            .get_node(&self.current_state)
+            .expect("ERROR: Invalid state ID");
+        let local_edges = state_graph
             .edges()
             .get(&self.current_state)
-            .expect("ERROR: Invalid state ID");
+            .expect("ERROR: Invalid state ID")
+            .values()
+            .map(|edge| (self.current_state, edge));
+        // Edges of the wildcard states covering the current one, after the local edges
+        let wildcard_edges = state_graph
+            .nodes()
+            .values()
+            .filter(|state| state.covers(current_state))
+            .filter_map(|state| {
+                state_graph
+                    .edges()
+                    .get(&state.id())
+                    .map(|edges| (state.id(), edges))
+            })
+            .flat_map(|(from, edges)| edges.values().map(move |edge| (from, edge)))
+            .filter(|(_, edge)| edge.next() != self.current_state);
+        let candidates: Vec<_> = local_edges
+            .chain(wildcard_edges)
+            .filter(|(_, edge)| {
+                matches!(
+                    state_graph.get_node(&edge.next()),
+                    Some(next) if next.r#type() != StateType::Wildcard
+                )
+            })
+            .collect();
+
+        // Every edge keeps track of its dwell time, even once another one fired
+        let mut fired = None;
+        for (from, edge) in candidates {
+            let key = (from, edge.next());
 
-        for edge in edges.values() {
-            if let Some(edge_trigger) = edge.trigger() {
-                if edge_trigger == trigger {
-                    self.try_execute(&StateEvent::OnExit, state_graph, pointer);
-                    self.current_state = edge.next();
-                    self.try_execute(&StateEvent::OnEnter, state_graph, pointer);
+            if !matches!(edge.trigger(), Some(trigger) if self.matches(trigger, edge.threshold, observation))
+            {
+                self.dwelling.remove(&key);
+                continue;
+            }
 
-                    return true;
+            let dwell = self.dwelling.entry(key).or_insert(Dwell {
+                since: observation.time,
+                frames: 0,
+            });
+            dwell.frames += 1;
+
+            let held = match edge.hold {
+                Some(hold) => hold.satisfied(dwell.since, dwell.frames, observation.time),
+                None => true,
+            };
+            let cooled_down = match (edge.cooldown, self.last_fired.get(&key)) {
+                (Some(ms), Some(last_fired)) => {
+                    observation.time.saturating_duration_since(*last_fired)
+                        >= Duration::from_millis(ms)
                 }
+                _ => true,
+            };
+
+            if fired.is_none() && held && cooled_down {
+                fired = Some((key, edge));
             }
         }
 
-        false
+        let (key, edge) = fired?;
+        let previous_state = self.current_state;
+
+        self.last_fired.insert(key, observation.time);
+        self.dwelling.clear();
+        // Sequences start over in every state, and one is used up once it fired
+        self.sign_history.clear();
+
+        self.current_state = edge.next();
+        self.entered_at = observation.time;
+
+        Some((
+            previous_state,
+            matches!(edge.trigger(), Some(trigger) if trigger.timed()),
+        ))
+    }
+
+    // Emergency stop, jumps to the given state without running any event
+    pub fn reset(&mut self, state: I, pointer: &mut PointerTracker) -> bool {
+        self.executor.stop(pointer);
+
+        if self.current_state == state {
+            return false;
+        }
+
+        self.current_state = state;
+        self.entered_at = Instant::now();
+        self.dwelling.clear();
+        self.sign_history.clear();
+
+        true
     }
 
-    pub fn trigger_misc_events<J: Eq + Hash + Display>(
+    fn matches(
         &self,
-        state_graph: &Graph<I, State<I>, ConditionalEdge<I, Option<J>>>,
+        trigger: &Trigger,
+        threshold: Option<f32>,
+        observation: &Observation,
+    ) -> bool {
+        match trigger {
+            Trigger::Sign(sign) => observation.recognized(threshold) == Some(sign.as_str()),
+            Trigger::AnyOf(signs) => matches!(
+                observation.recognized(threshold),
+                Some(recognized) if signs.iter().any(|sign| sign == recognized)
+            ),
+            Trigger::Sequence { signs, window } => {
+                // The last sign of the sequence has to be the current one
+                if signs.is_empty()
+                    || observation.recognized(threshold) != signs.last().map(String::as_str)
+                {
+                    return false;
+                }
+
+                // The signs recognized at the edge's threshold, without repetitions
+                let mut recognized: Vec<&SeenSign> = Vec::new();
+                for seen in self
+                    .sign_history
+                    .iter()
+                    .filter(|seen| seen.peak > threshold.unwrap_or(seen.threshold))
+                {
+                    if !matches!(recognized.last(), Some(last) if last.sign == seen.sign) {
+                        recognized.push(seen);
+                    }
+                }
+                if recognized.len() < signs.len() {
+                    return false;
+                }
+
+                let recent = &recognized[recognized.len() - signs.len()..];
+                let first = recent[0].since;
+                let last = recent[recent.len() - 1].since;
+
+                recent
+                    .iter()
+                    .zip(signs)
+                    .all(|(seen, sign)| seen.sign == *sign)
+                    && last.saturating_duration_since(first) <= Duration::from_millis(*window)
+            }
+            Trigger::TwoHands {
+                left,
+                right,
+                distance,
+            } => {
+                let shown = |hand: Handedness, sign: &String| {
+                    observation.hand_signs.get(&hand) == Some(&sign.as_str())
+                };
+
+                shown(Handedness::Left, left)
+                    && shown(Handedness::Right, right)
+                    && (distance.is_none() || observation.hand_distance == *distance)
+            }
+            Trigger::Swipe(direction) => observation.swipe == Some(*direction),
+            Trigger::Depth(motion) => observation.depth_motions.contains(motion),
+            Trigger::Trajectory(template) => observation.trajectory == Some(template.as_str()),
+            Trigger::Timeout(ms) => {
+                observation.time.saturating_duration_since(self.entered_at)
+                    >= Duration::from_millis(*ms)
+            }
+            Trigger::HandLost(ms) => {
+                // Counted from entering the state at the earliest
+                let since = self.hand_last_seen.max(self.entered_at);
+
+                !observation.hand_visible
+                    && observation.time.saturating_duration_since(since)
+                        >= Duration::from_millis(*ms)
+            }
+        }
+    }
+
+    fn remember_sign(&mut self, sign: &str, observation: &Observation) {
+        if let Some(last) = self.sign_history.back_mut() {
+            if last.sign == sign {
+                last.peak = last.peak.max(observation.probability);
+                return;
+            }
+        }
+
+        self.sign_history.push_back(SeenSign {
+            sign: sign.to_string(),
+            since: observation.time,
+            peak: observation.probability,
+            threshold: observation.threshold,
+        });
+        if self.sign_history.len() > MAX_SIGN_HISTORY {
+            self.sign_history.pop_front();
+        }
+    }
+
+    pub fn trigger_misc_events(
+        &mut self,
+        state_graph: &Graph<I, State<I>, ConditionalEdge<I, Option<Trigger>>>,
         pointer: &mut PointerTracker,
     ) {
         if let Some(current_state) = state_graph.get_node(&self.current_state) {
-            for (_, cmd) in current_state
-                .events
-                .iter()
-                .filter(|(e, _)| (*e).ne(&StateEvent::OnExit) && (*e).ne(&StateEvent::OnEnter))
-            {
-                cmd.execute(pointer);
+            self.executor.set_state(&current_state.name);
+
+            for (_, cmd) in current_state.events.iter().filter(|(e, _)| {
+                !matches!(
+                    e,
+                    StateEvent::OnEnter | StateEvent::OnExit | StateEvent::OnTimeout
+                )
+            }) {
+                self.executor.execute(cmd, pointer);
             }
         }
     }
 
-    fn try_execute<J: Eq + Hash + Display>(
+    fn try_execute(
         &mut self,
+        state: I,
         event: &StateEvent,
-        state_graph: &Graph<I, State<I>, ConditionalEdge<I, Option<J>>>,
+        state_graph: &Graph<I, State<I>, ConditionalEdge<I, Option<Trigger>>>,
         pointer: &mut PointerTracker,
     ) {
-        if let Some(cmd) = state_graph
-            .get_node(&self.current_state)
-            .expect("ERROR: Invalid current state ID.")
-            .get_command(event)
-        {
-            cmd.execute(pointer);
+        let state = state_graph
+            .get_node(&state)
+            .expect("ERROR: Invalid current state ID.");
+
+        if let Some(cmd) = state.get_command(event) {
+            self.executor.set_state(&state.name);
+            self.executor.execute(cmd, pointer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    use crate::common::state::{
+        ConditionalEdge, Hold, Observation, State, StateMachine, StateType, Trigger,
+    };
+    use crate::common::{CommandExecutor, Graph, HandDistance, Handedness};
+
+    type StateGraph = Graph<i32, State<i32>, ConditionalEdge<i32, Option<Trigger>>>;
+
+    fn graph(states: &[i32], edges: Vec<(i32, i32, Trigger)>) -> StateGraph {
+        let mut graph = StateGraph::default();
+        for state in states {
+            graph.add_node(State::new(*state));
+        }
+        for (from, to, trigger) in edges {
+            graph.add_edge(&from, ConditionalEdge::new(to, Some(trigger)));
+        }
+
+        graph
+    }
+
+    fn machine() -> (StateMachine<i32>, Instant) {
+        let machine = StateMachine::new(0, CommandExecutor::new(true));
+
+        (machine, Instant::now())
+    }
+
+    fn observation(start: Instant, ms: u64) -> Observation<'static> {
+        Observation {
+            sign: None,
+            probability: 0.0,
+            threshold: 0.5,
+            hand_signs: HashMap::new(),
+            hand_distance: None,
+            swipe: None,
+            depth_motions: Vec::new(),
+            trajectory: None,
+            hand_visible: true,
+            time: start + Duration::from_millis(ms),
+        }
+    }
+
+    fn signed(start: Instant, ms: u64, sign: &'static str) -> Observation<'static> {
+        Observation {
+            sign: Some(sign),
+            probability: 1.0,
+            ..observation(start, ms)
+        }
+    }
+
+    fn fist() -> Trigger {
+        Trigger::Sign(String::from("FIST"))
+    }
+
+    #[test]
+    fn timeouts_count_from_entering_the_state() {
+        let graph = graph(
+            &[0, 1],
+            vec![(0, 1, Trigger::Timeout(500)), (1, 0, Trigger::Timeout(500))],
+        );
+        let (mut machine, start) = machine();
+
+        assert_eq!(machine.advance(&graph, &observation(start, 200)), None);
+        assert_eq!(
+            machine.advance(&graph, &observation(start, 500)),
+            Some((0, true))
+        );
+        assert_eq!(*machine.current_state(), 1);
+
+        assert_eq!(machine.advance(&graph, &observation(start, 700)), None);
+        assert_eq!(
+            machine.advance(&graph, &observation(start, 1000)),
+            Some((1, true))
+        );
+    }
+
+    #[test]
+    fn hand_lost_counts_from_the_last_sighting() {
+        let graph = graph(&[0, 1], vec![(0, 1, Trigger::HandLost(300))]);
+        let (mut machine, start) = machine();
+        let lost = |ms| Observation {
+            hand_visible: false,
+            ..observation(start, ms)
+        };
+
+        assert_eq!(machine.advance(&graph, &observation(start, 100)), None);
+        assert_eq!(machine.advance(&graph, &lost(300)), None);
+        assert_eq!(machine.advance(&graph, &lost(400)), Some((0, true)));
+        assert_eq!(*machine.current_state(), 1);
+    }
+
+    #[test]
+    fn local_edges_come_before_wildcard_ones() {
+        let mut graph = graph(&[0, 1, 2, 3], vec![(0, 1, fist()), (2, 3, fist())]);
+        graph
+            .get_node_mut(&2)
+            .unwrap()
+            .set_type(StateType::Wildcard);
+        let (mut machine, start) = machine();
+
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 0, "FIST")),
+            Some((0, false))
+        );
+        assert_eq!(*machine.current_state(), 1);
+
+        // Without a local edge the wildcard one applies
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 10, "FIST")),
+            Some((1, false))
+        );
+        assert_eq!(*machine.current_state(), 3);
+
+        // Wildcard edges leading to the current state are skipped
+        assert_eq!(machine.advance(&graph, &signed(start, 20, "FIST")), None);
+        assert_eq!(*machine.current_state(), 3);
+    }
+
+    #[test]
+    fn tagged_wildcards_cover_matching_states_only() {
+        let mut graph = graph(&[0, 1, 2], vec![(2, 1, fist())]);
+        let wildcard = graph.get_node_mut(&2).unwrap();
+        wildcard.set_type(StateType::Wildcard);
+        wildcard.tags = vec![String::from("menu")];
+        let (mut machine, start) = machine();
+
+        assert_eq!(machine.advance(&graph, &signed(start, 0, "FIST")), None);
+
+        graph.get_node_mut(&0).unwrap().tags = vec![String::from("menu")];
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 10, "FIST")),
+            Some((0, false))
+        );
+    }
+
+    #[test]
+    fn holds_restart_when_the_trigger_stops_matching() {
+        let mut graph = graph(&[0, 1], vec![(0, 1, fist())]);
+        graph.get_edge_mut(&0, &1).unwrap().hold = Some(Hold::Duration(300));
+        let (mut machine, start) = machine();
+
+        assert_eq!(machine.advance(&graph, &signed(start, 0, "FIST")), None);
+        assert_eq!(machine.advance(&graph, &signed(start, 200, "FIST")), None);
+        assert_eq!(machine.advance(&graph, &observation(start, 250)), None);
+        assert_eq!(machine.advance(&graph, &signed(start, 300, "FIST")), None);
+        assert_eq!(machine.advance(&graph, &signed(start, 500, "FIST")), None);
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 600, "FIST")),
+            Some((0, false))
+        );
+    }
+
+    #[test]
+    fn edges_wait_for_their_cooldown() {
+        let mut graph = graph(
+            &[0, 1],
+            vec![(0, 1, fist()), (1, 0, Trigger::Sign(String::from("PALM")))],
+        );
+        graph.get_edge_mut(&0, &1).unwrap().cooldown = Some(1000);
+        let (mut machine, start) = machine();
+
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 0, "FIST")),
+            Some((0, false))
+        );
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 100, "PALM")),
+            Some((1, false))
+        );
+        assert_eq!(machine.advance(&graph, &signed(start, 200, "FIST")), None);
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 1000, "FIST")),
+            Some((0, false))
+        );
+    }
+
+    fn sequence(window: u64) -> Trigger {
+        Trigger::Sequence {
+            signs: vec![String::from("FIST"), String::from("PALM")],
+            window,
         }
     }
+
+    #[test]
+    fn sequences_expire_after_their_window() {
+        let graph = graph(&[0, 1], vec![(0, 1, sequence(500))]);
+        let (mut machine, start) = machine();
+
+        assert_eq!(machine.advance(&graph, &signed(start, 0, "FIST")), None);
+        assert_eq!(machine.advance(&graph, &signed(start, 600, "PALM")), None);
+        assert_eq!(machine.advance(&graph, &signed(start, 700, "FIST")), None);
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 900, "PALM")),
+            Some((0, false))
+        );
+    }
+
+    #[test]
+    fn sequences_use_the_edge_threshold() {
+        let mut graph = graph(&[0, 1], vec![(0, 1, sequence(1000))]);
+        graph.get_edge_mut(&0, &1).unwrap().threshold = Some(0.8);
+        let (mut machine, start) = machine();
+        let weak = |ms, sign| Observation {
+            probability: 0.7,
+            ..signed(start, ms, sign)
+        };
+
+        // Recognized at the global threshold but not at the edge's
+        assert_eq!(machine.advance(&graph, &weak(0, "FIST")), None);
+        assert_eq!(machine.advance(&graph, &signed(start, 100, "PALM")), None);
+
+        assert_eq!(machine.advance(&graph, &signed(start, 200, "FIST")), None);
+        // A weak sign in between does not break the sequence
+        assert_eq!(machine.advance(&graph, &weak(300, "OK")), None);
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 400, "PALM")),
+            Some((0, false))
+        );
+    }
+
+    #[test]
+    fn sequences_start_over_in_every_state() {
+        let graph = graph(&[0, 1, 2], vec![(0, 1, fist()), (1, 2, sequence(1000))]);
+        let (mut machine, start) = machine();
+
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 0, "FIST")),
+            Some((0, false))
+        );
+        assert_eq!(machine.advance(&graph, &signed(start, 100, "PALM")), None);
+        assert_eq!(machine.advance(&graph, &signed(start, 200, "FIST")), None);
+        assert_eq!(
+            machine.advance(&graph, &signed(start, 300, "PALM")),
+            Some((1, false))
+        );
+    }
+
+    #[test]
+    fn two_hands_need_both_signs_and_the_distance() {
+        let graph = graph(
+            &[0, 1],
+            vec![(
+                0,
+                1,
+                Trigger::TwoHands {
+                    left: String::from("FIST"),
+                    right: String::from("PALM"),
+                    distance: Some(HandDistance::Apart),
+                },
+            )],
+        );
+        let (mut machine, start) = machine();
+        let hands = |ms, left, right, distance| Observation {
+            hand_signs: HashMap::from([(Handedness::Left, left), (Handedness::Right, right)]),
+            hand_distance: distance,
+            ..observation(start, ms)
+        };
+
+        assert_eq!(
+            machine.advance(&graph, &hands(0, "PALM", "FIST", Some(HandDistance::Apart))),
+            None
+        );
+        assert_eq!(
+            machine.advance(
+                &graph,
+                &hands(10, "FIST", "PALM", Some(HandDistance::Close))
+            ),
+            None
+        );
+        assert_eq!(
+            machine.advance(&graph, &hands(20, "FIST", "PALM", None)),
+            None
+        );
+
+        let one_hand = Observation {
+            hand_signs: HashMap::from([(Handedness::Left, "FIST")]),
+            ..observation(start, 30)
+        };
+        assert_eq!(machine.advance(&graph, &one_hand), None);
+
+        assert_eq!(
+            machine.advance(
+                &graph,
+                &hands(40, "FIST", "PALM", Some(HandDistance::Apart))
+            ),
+            Some((0, false))
+        );
+    }
 }
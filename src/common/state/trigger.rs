@@ -0,0 +1,257 @@
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use strum_macros::{Display, EnumDiscriminants, EnumString, EnumVariantNames};
+
+use crate::common::{DepthMotion, Direction, HandDistance};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, EnumDiscriminants)]
+#[strum_discriminants(derive(Display, EnumString, EnumVariantNames))]
+#[serde(remote = "Self")]
+pub enum Trigger {
+    Sign(String),
+    AnyOf(Vec<String>),
+    // The signs recognized in this order, the window is the milliseconds between the first and the last
+    Sequence {
+        signs: Vec<String>,
+        window: u64,
+    },
+    // A sign on each hand at once, and optionally how far apart the hands are
+    TwoHands {
+        left: String,
+        right: String,
+        #[serde(default)]
+        distance: Option<HandDistance>,
+    },
+    Swipe(Direction),
+    // A landmark moved towards the camera or back
+    Depth(DepthMotion),
+    // A recorded trajectory template
+    Trajectory(String),
+    // Milliseconds spent in the current state
+    Timeout(u64),
+    // Milliseconds without a tracked hand
+    HandLost(u64),
+}
+
+impl Trigger {
+    // Time based triggers also fire the OnTimeout event of the state they leave
+    pub fn timed(&self) -> bool {
+        matches!(self, Trigger::Timeout(_) | Trigger::HandLost(_))
+    }
+
+    pub fn signs(&self) -> Vec<&str> {
+        match self {
+            Trigger::Sign(sign) => vec![sign.as_str()],
+            Trigger::AnyOf(signs) | Trigger::Sequence { signs, .. } => {
+                signs.iter().map(String::as_str).collect()
+            }
+            Trigger::TwoHands { left, right, .. } => vec![left.as_str(), right.as_str()],
+            Trigger::Swipe(_)
+            | Trigger::Depth(_)
+            | Trigger::Trajectory(_)
+            | Trigger::Timeout(_)
+            | Trigger::HandLost(_) => Vec::new(),
+        }
+    }
+
+    // Follows a renamed sign, None once the sign is deleted
+    pub fn rename_sign(self, old: &str, new: Option<&str>) -> Option<Trigger> {
+        let rename = |signs: Vec<String>| -> Vec<String> {
+            signs
+                .into_iter()
+                .filter_map(|sign| {
+                    if sign == old {
+                        new.map(String::from)
+                    } else {
+                        Some(sign)
+                    }
+                })
+                .collect()
+        };
+
+        match self {
+            Trigger::Sign(sign) if sign == old => new.map(|new| Trigger::Sign(new.to_string())),
+            // The remaining signs still make sense on their own
+            Trigger::AnyOf(signs) => {
+                let signs = rename(signs);
+
+                if signs.is_empty() {
+                    None
+                } else {
+                    Some(Trigger::AnyOf(signs))
+                }
+            }
+            // Unlike a sequence missing one of its steps
+            Trigger::Sequence { signs, window } => {
+                let len = signs.len();
+                let signs = rename(signs);
+
+                if signs.len() == len {
+                    Some(Trigger::Sequence { signs, window })
+                } else {
+                    None
+                }
+            }
+            // Both hands are needed
+            Trigger::TwoHands {
+                left,
+                right,
+                distance,
+            } => {
+                let mut signs = rename(vec![left, right]).into_iter();
+
+                match (signs.next(), signs.next()) {
+                    (Some(left), Some(right)) => Some(Trigger::TwoHands {
+                        left,
+                        right,
+                        distance,
+                    }),
+                    _ => None,
+                }
+            }
+            trigger => Some(trigger),
+        }
+    }
+
+    // None once the trajectory template is deleted
+    pub fn delete_template(self, name: &str) -> Option<Trigger> {
+        match self {
+            Trigger::Trajectory(template) if template == name => None,
+            trigger => Some(trigger),
+        }
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Sign(sign) => write!(f, "{}", sign),
+            Trigger::AnyOf(signs) => write!(f, "{}", signs.join(" | ")),
+            Trigger::Sequence { signs, window } => {
+                write!(f, "{} within {} ms", signs.join(" → "), window)
+            }
+            Trigger::TwoHands {
+                left,
+                right,
+                distance,
+            } => match distance {
+                Some(distance) => write!(f, "{} + {}, {}", left, right, distance),
+                None => write!(f, "{} + {}", left, right),
+            },
+            Trigger::Swipe(direction) => write!(f, "Swipe {}", direction),
+            Trigger::Depth(motion) => write!(f, "{}", motion),
+            Trigger::Trajectory(template) => write!(f, "Draw {}", template),
+            Trigger::Timeout(ms) => write!(f, "After {} ms", ms),
+            Trigger::HandLost(ms) => write!(f, "No hand for {} ms", ms),
+        }
+    }
+}
+
+// Sign triggers are stored as the plain sign name, like before the other triggers existed
+impl Serialize for Trigger {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Trigger::Sign(sign) => sign.serialize(serializer),
+            _ => Trigger::serialize(self, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Trigger {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Sign(String),
+            #[serde(with = "Trigger")]
+            Tagged(Trigger),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Sign(sign) => Trigger::Sign(sign),
+            Repr::Tagged(trigger) => trigger,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::state::Trigger;
+    use crate::common::DepthMotion;
+
+    #[test]
+    fn plain_sign_names() {
+        let trigger: Trigger = serde_yaml::from_str("FIST").unwrap();
+        assert_eq!(trigger, Trigger::Sign(String::from("FIST")));
+
+        let yaml = serde_yaml::to_string(&trigger).unwrap();
+        assert_eq!(serde_yaml::from_str::<Trigger>(&yaml).unwrap(), trigger);
+    }
+
+    #[test]
+    fn timed_triggers() {
+        let trigger: Trigger = serde_yaml::from_str("HandLost: 500").unwrap();
+        assert_eq!(trigger, Trigger::HandLost(500));
+
+        let yaml = serde_yaml::to_string(&Trigger::Timeout(250)).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Trigger>(&yaml).unwrap(),
+            Trigger::Timeout(250)
+        );
+    }
+
+    #[test]
+    fn sign_expressions() {
+        let trigger: Trigger = serde_yaml::from_str("AnyOf: [FIST, PALM]").unwrap();
+        assert_eq!(trigger.signs(), vec!["FIST", "PALM"]);
+
+        let trigger: Trigger =
+            serde_yaml::from_str("Sequence: {signs: [FIST, PALM], window: 800}").unwrap();
+        assert_eq!(trigger.to_string(), "FIST → PALM within 800 ms");
+        assert!(!trigger.timed());
+    }
+
+    #[test]
+    fn depth_motions() {
+        let trigger: Trigger = serde_yaml::from_str("Depth: Tap").unwrap();
+        assert_eq!(trigger, Trigger::Depth(DepthMotion::Tap));
+        assert_eq!(trigger.to_string(), "Tap");
+        assert!(trigger.signs().is_empty());
+    }
+
+    #[test]
+    fn rename_signs() {
+        let any_of = Trigger::AnyOf(vec![String::from("FIST"), String::from("PALM")]);
+        let sequence = Trigger::Sequence {
+            signs: vec![String::from("FIST"), String::from("PALM")],
+            window: 800,
+        };
+
+        assert_eq!(
+            any_of
+                .clone()
+                .rename_sign("FIST", Some("ROCK"))
+                .unwrap()
+                .signs(),
+            vec!["ROCK", "PALM"]
+        );
+        assert_eq!(
+            any_of.rename_sign("FIST", None),
+            Some(Trigger::AnyOf(vec![String::from("PALM")]))
+        );
+        assert_eq!(sequence.rename_sign("PALM", None), None);
+    }
+
+    #[test]
+    fn two_hands() {
+        let trigger: Trigger =
+            serde_yaml::from_str("TwoHands: {left: PINCH, right: PINCH, distance: Apart}").unwrap();
+        assert_eq!(trigger.to_string(), "PINCH + PINCH, Apart");
+        assert_eq!(trigger.signs(), vec!["PINCH", "PINCH"]);
+
+        let renamed = trigger.clone().rename_sign("PINCH", Some("OK")).unwrap();
+        assert_eq!(renamed.to_string(), "OK + OK, Apart");
+        assert_eq!(trigger.rename_sign("PINCH", None), None);
+    }
+}
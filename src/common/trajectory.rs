@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+use crate::common::Point2F;
+
+// The $1 unistroke recognizer, see Wobbrock et al., "Gestures without Libraries, Toolkits or Training"
+const RESAMPLED_POINTS: usize = 64;
+const SQUARE_SIZE: f32 = 1f32;
+const ANGLE_RANGE: f32 = PI / 4f32;
+const ANGLE_PRECISION: f32 = PI / 90f32;
+// Narrower strokes are lines, scaled uniformly so their jitter is not blown up, as in $N
+const ONE_D_RATIO: f32 = 0.3f32;
+
+// Stroke segmentation, in fractions of the frame
+const MIN_STEP: f32 = 0.005f32;
+const MIN_LENGTH: f32 = 0.2f32;
+const MAX_POINTS: usize = 256;
+// A stroke ends once the hand rests for this long
+const PAUSE: Duration = Duration::from_millis(200);
+
+// A recorded trajectory, resampled, rotated, scaled and centered
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Template {
+    points: Vec<Point2F>,
+}
+
+impl Template {
+    pub fn points(&self) -> &[Point2F] {
+        self.points.as_ref()
+    }
+
+    // From 0 to 1, the higher the closer
+    pub fn score(&self, candidate: &Template) -> f32 {
+        let distance = distance_at_best_angle(&candidate.points, &self.points);
+        let half_diagonal = 0.5f32 * (2f32 * SQUARE_SIZE * SQUARE_SIZE).sqrt();
+
+        1f32 - distance / half_diagonal
+    }
+}
+
+impl From<&[Point2F]> for Template {
+    fn from(points: &[Point2F]) -> Self {
+        let points = resample(points, RESAMPLED_POINTS);
+        let points = rotate_by(&points, -indicative_angle(&points));
+        let points = scale_to_square(&points);
+        let points = translate_to_origin(&points);
+
+        Template { points }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrajectoryDictionary {
+    // The followed landmark, the hand center if none
+    pub landmark: Option<usize>,
+    // Lowest score a trajectory is recognized with
+    pub min_score: f32,
+    templates: BTreeMap<String, Template>,
+}
+
+impl Default for TrajectoryDictionary {
+    fn default() -> Self {
+        TrajectoryDictionary {
+            landmark: None,
+            min_score: 0.8f32,
+            templates: BTreeMap::new(),
+        }
+    }
+}
+
+impl TrajectoryDictionary {
+    pub fn templates(&self) -> &BTreeMap<String, Template> {
+        &self.templates
+    }
+
+    pub fn templates_mut(&mut self) -> &mut BTreeMap<String, Template> {
+        &mut self.templates
+    }
+
+    // The best matching template and its score
+    pub fn recognize(&self, points: &[Point2F]) -> Option<(&String, f32)> {
+        let candidate = Template::from(points);
+
+        self.templates
+            .iter()
+            .map(|(name, template)| (name, template.score(&candidate)))
+            .filter(|(_, score)| *score >= self.min_score)
+            .max_by_key(|(_, score)| OrderedFloat(*score))
+    }
+}
+
+// Splits the followed point's motion into strokes, separated by rests or lost hands
+#[derive(Default)]
+pub struct StrokeTracker {
+    points: Vec<Point2F>,
+    last_moved: Option<Instant>,
+}
+
+impl StrokeTracker {
+    // The finished stroke, if long enough
+    pub fn track(&mut self, point: Option<Point2F>, time: Instant) -> Option<Vec<Point2F>> {
+        let point = match point {
+            Some(point) => point,
+            None => return self.finish(),
+        };
+
+        let moved = match self.points.last() {
+            Some(last) => last.distance(&point) >= MIN_STEP,
+            None => true,
+        };
+        let rested = match self.last_moved {
+            Some(last_moved) => time.saturating_duration_since(last_moved) >= PAUSE,
+            None => false,
+        };
+
+        if moved {
+            self.points.push(point);
+            self.last_moved = Some(time);
+            if self.points.len() > MAX_POINTS {
+                self.points.remove(0);
+            }
+
+            None
+        } else if rested {
+            self.finish()
+        } else {
+            None
+        }
+    }
+
+    fn finish(&mut self) -> Option<Vec<Point2F>> {
+        let points = std::mem::take(&mut self.points);
+        self.last_moved = None;
+
+        if path_length(&points) >= MIN_LENGTH {
+            Some(points)
+        } else {
+            None
+        }
+    }
+}
+
+fn path_length(points: &[Point2F]) -> f32 {
+    points.windows(2).map(|w| w[0].distance(&w[1])).sum()
+}
+
+fn centroid(points: &[Point2F]) -> Point2F {
+    let n = points.len().max(1) as f32;
+    let (x, y) = points
+        .iter()
+        .fold((0f32, 0f32), |(x, y), p| (x + p.x, y + p.y));
+
+    Point2F::new(x / n, y / n)
+}
+
+fn resample(points: &[Point2F], n: usize) -> Vec<Point2F> {
+    let interval = path_length(points) / (n - 1) as f32;
+    let mut resampled = Vec::with_capacity(n);
+    let mut remaining = points.to_vec();
+    let mut distance = 0f32;
+    let mut i = 1;
+
+    if let Some(first) = points.first() {
+        resampled.push(*first);
+    }
+
+    while i < remaining.len() {
+        let (a, b) = (remaining[i - 1], remaining[i]);
+        let d = a.distance(&b);
+
+        if interval > 0f32 && distance + d >= interval {
+            let t = (interval - distance) / d;
+            let q = Point2F::new(a.x + t * (b.x - a.x), a.y + t * (b.y - a.y));
+
+            resampled.push(q);
+            // The new point is the start of the next segment
+            remaining.insert(i, q);
+            distance = 0f32;
+        } else {
+            distance += d;
+        }
+        i += 1;
+    }
+
+    // Rounding errors may leave the last point out
+    while resampled.len() < n {
+        match points.last() {
+            Some(last) => resampled.push(*last),
+            None => resampled.push(Point2F::default()),
+        }
+    }
+    resampled.truncate(n);
+
+    resampled
+}
+
+fn indicative_angle(points: &[Point2F]) -> f32 {
+    let c = centroid(points);
+
+    (c.y - points[0].y).atan2(c.x - points[0].x)
+}
+
+fn rotate_by(points: &[Point2F], angle: f32) -> Vec<Point2F> {
+    let c = centroid(points);
+    let (sin, cos) = angle.sin_cos();
+
+    points
+        .iter()
+        .map(|p| {
+            Point2F::new(
+                (p.x - c.x) * cos - (p.y - c.y) * sin + c.x,
+                (p.x - c.x) * sin + (p.y - c.y) * cos + c.y,
+            )
+        })
+        .collect()
+}
+
+fn scale_to_square(points: &[Point2F]) -> Vec<Point2F> {
+    let (min_x, max_x, min_y, max_y) = points.iter().fold(
+        (f32::MAX, f32::MIN, f32::MAX, f32::MIN),
+        |(min_x, max_x, min_y, max_y), p| {
+            (
+                min_x.min(p.x),
+                max_x.max(p.x),
+                min_y.min(p.y),
+                max_y.max(p.y),
+            )
+        },
+    );
+    let mut width = (max_x - min_x).max(f32::EPSILON);
+    let mut height = (max_y - min_y).max(f32::EPSILON);
+    if width.min(height) / width.max(height) <= ONE_D_RATIO {
+        width = width.max(height);
+        height = width;
+    }
+
+    points
+        .iter()
+        .map(|p| Point2F::new(p.x * SQUARE_SIZE / width, p.y * SQUARE_SIZE / height))
+        .collect()
+}
+
+fn translate_to_origin(points: &[Point2F]) -> Vec<Point2F> {
+    let c = centroid(points);
+
+    points
+        .iter()
+        .map(|p| Point2F::new(p.x - c.x, p.y - c.y))
+        .collect()
+}
+
+fn path_distance(a: &[Point2F], b: &[Point2F]) -> f32 {
+    let sum: f32 = a.iter().zip(b).map(|(a, b)| a.distance(b)).sum();
+
+    sum / a.len().max(1) as f32
+}
+
+fn distance_at_angle(points: &[Point2F], template: &[Point2F], angle: f32) -> f32 {
+    path_distance(&rotate_by(points, angle), template)
+}
+
+// Golden section search for the rotation that fits the template best
+fn distance_at_best_angle(points: &[Point2F], template: &[Point2F]) -> f32 {
+    let phi = 0.5f32 * (5f32.sqrt() - 1f32);
+    let (mut a, mut b) = (-ANGLE_RANGE, ANGLE_RANGE);
+    let mut x1 = phi * a + (1f32 - phi) * b;
+    let mut f1 = distance_at_angle(points, template, x1);
+    let mut x2 = (1f32 - phi) * a + phi * b;
+    let mut f2 = distance_at_angle(points, template, x2);
+
+    while (b - a).abs() > ANGLE_PRECISION {
+        if f1 < f2 {
+            b = x2;
+            x2 = x1;
+            f2 = f1;
+            x1 = phi * a + (1f32 - phi) * b;
+            f1 = distance_at_angle(points, template, x1);
+        } else {
+            a = x1;
+            x1 = x2;
+            f1 = f2;
+            x2 = (1f32 - phi) * a + phi * b;
+            f2 = distance_at_angle(points, template, x2);
+        }
+    }
+
+    f1.min(f2)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+    use std::time::{Duration, Instant};
+
+    use crate::common::trajectory::{StrokeTracker, Template, TrajectoryDictionary};
+    use crate::common::Point2F;
+
+    fn circle(radius: f32, start: f32) -> Vec<Point2F> {
+        (0..=40)
+            .map(|i| start + i as f32 * 2f32 * PI / 40f32)
+            .map(|angle| Point2F::new(0.5f32 + radius * angle.cos(), 0.5f32 + radius * angle.sin()))
+            .collect()
+    }
+
+    fn zigzag(width: f32) -> Vec<Point2F> {
+        (0..=6)
+            .map(|i| {
+                Point2F::new(
+                    0.2f32 + i as f32 * width / 6f32,
+                    if i % 2 == 0 { 0.4f32 } else { 0.6f32 },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recognize_templates() {
+        let mut dictionary = TrajectoryDictionary::default();
+        dictionary.templates_mut().insert(
+            String::from("Circle"),
+            Template::from(circle(0.2f32, 0f32).as_slice()),
+        );
+        dictionary.templates_mut().insert(
+            String::from("Zigzag"),
+            Template::from(zigzag(0.6f32).as_slice()),
+        );
+
+        // Smaller, and started from another point
+        let (name, _) = dictionary.recognize(&circle(0.1f32, 0.3f32)).unwrap();
+        assert_eq!(name, "Circle");
+
+        let (name, score) = dictionary.recognize(&zigzag(0.4f32)).unwrap();
+        assert_eq!(name, "Zigzag");
+        assert!(score > 0.8f32);
+    }
+
+    #[test]
+    fn lines_keep_their_proportions() {
+        let line: Vec<Point2F> = (0..=20)
+            .map(|i| {
+                Point2F::new(
+                    0.2f32 + i as f32 * 0.03f32,
+                    0.5f32 + (i % 2) as f32 * 0.005f32,
+                )
+            })
+            .collect();
+        let template = Template::from(line.as_slice());
+
+        assert!(template.points().iter().all(|p| p.y.abs() < 0.05f32));
+
+        let mut dictionary = TrajectoryDictionary::default();
+        dictionary
+            .templates_mut()
+            .insert(String::from("Line"), template);
+        dictionary.templates_mut().insert(
+            String::from("Zigzag"),
+            Template::from(zigzag(0.6f32).as_slice()),
+        );
+
+        let straight = [Point2F::new(0.1f32, 0.3f32), Point2F::new(0.7f32, 0.3f32)];
+        let (name, score) = dictionary.recognize(&straight).unwrap();
+        assert_eq!(name, "Line");
+        assert!(score > 0.9f32);
+    }
+
+    #[test]
+    fn strokes_end_on_rest() {
+        let mut tracker = StrokeTracker::default();
+        let start = Instant::now();
+
+        for (i, point) in circle(0.2f32, 0f32).into_iter().enumerate() {
+            let time = start + Duration::from_millis(33 * i as u64);
+            assert!(tracker.track(Some(point), time).is_none());
+        }
+
+        let rest = start + Duration::from_secs(5);
+        let stroke = tracker.track(Some(Point2F::new(0.7f32, 0.5f32)), rest);
+        assert_eq!(stroke.map(|stroke| stroke.len()), Some(41));
+    }
+}
@@ -27,6 +27,11 @@ impl Vec2F {
     pub fn magnitude(&self) -> f32 {
         self.dot(self).sqrt()
     }
+
+    // Positive when the other vector is clockwise from this one, in image coordinates
+    pub fn cross(&self, other: &Vec2F) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
 }
 
 impl Default for Vec2F {
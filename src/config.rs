@@ -5,14 +5,20 @@ use std::io::prelude::Read;
 use std::io::Write;
 
 use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
 
 use crate::common::state::State;
-use crate::common::SignDictionary;
+use crate::common::{
+    CalibrationPose, Classifier, FeatureConfig, Handedness, PushConfig, SignDictionary,
+    SwipeConfig, TrajectoryDictionary,
+};
 use crate::{ConditionalGraph, StateIndex};
 
 // pub const INITIAL_STATE_INDEX: StateIndex = StateIndex::MIN;
 pub const INITIAL_STATE_INDEX: StateIndex = 0;
 pub const INITIAL_STATE_NAME: &str = "Start";
+pub const SIGN_CAPTURE_FRAMES: usize = 30;
+pub const CALIBRATION_FRAMES: usize = 30;
 
 #[derive(Debug)]
 pub enum Error {
@@ -47,14 +53,100 @@ impl From<serde_yaml::Error> for Error {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Display, EnumString)]
+#[strum(ascii_case_insensitive)]
+pub enum FrameFormat {
+    MJPEG,
+    YUYV,
+}
+
+impl From<FrameFormat> for nokhwa::FrameFormat {
+    fn from(format: FrameFormat) -> Self {
+        match format {
+            FrameFormat::MJPEG => nokhwa::FrameFormat::MJPEG,
+            FrameFormat::YUYV => nokhwa::FrameFormat::YUYV,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub index: usize,
+    pub width: u32,
+    pub height: u32,
+    pub format: FrameFormat,
+    pub fps: u32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            index: 0,
+            width: 640,
+            height: 480,
+            format: FrameFormat::MJPEG,
+            fps: crate::FPS as u32,
+        }
+    }
+}
+
+// Requested from the window, done by the core with the next frames
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capture {
+    // The next stroke becomes the named trajectory template
+    Trajectory(String),
+    // The features of the next frames with a hand, added to the dictionary under the name
+    Sign {
+        name: String,
+        frames: usize,
+    },
+    // The measurements of the next frames with a hand, for the calibration
+    Calibration {
+        pose: CalibrationPose,
+        frames: usize,
+    },
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     sign_dictionary: SignDictionary,
+    #[serde(default)]
+    trajectory_dictionary: TrajectoryDictionary,
     state_graph: ConditionalGraph,
     last_node_id: i32,
 
     pub sign_switching_smoothness: f32,
     pub sign_probability_threshold: f32,
+
+    // Thresholds the features of the hand are recognized with
+    #[serde(default)]
+    pub features: FeatureConfig,
+
+    // Recognizes the learned signs from their examples
+    #[serde(default)]
+    pub classifier: Classifier,
+
+    // The hand moving the pointer and drawing swipes and trajectories, the most confident one if none
+    #[serde(default)]
+    pub primary_hand: Option<Handedness>,
+
+    // Releases everything held and goes back to the initial state from anywhere
+    #[serde(default)]
+    pub panic_sign: Option<String>,
+
+    #[serde(default)]
+    pub swipe: SwipeConfig,
+
+    #[serde(default)]
+    pub push: PushConfig,
+
+    #[serde(default)]
+    pub camera: CameraConfig,
+
+    #[serde(skip)]
+    pub capture: Option<Capture>,
 }
 
 impl Config {
@@ -73,6 +165,14 @@ impl Config {
         &mut self.sign_dictionary
     }
 
+    pub fn trajectory_dictionary(&self) -> &TrajectoryDictionary {
+        &self.trajectory_dictionary
+    }
+
+    pub fn trajectory_dictionary_mut(&mut self) -> &mut TrajectoryDictionary {
+        &mut self.trajectory_dictionary
+    }
+
     pub fn state_graph(&self) -> &ConditionalGraph {
         &self.state_graph
     }
@@ -119,7 +219,24 @@ impl Config {
 
         file.read_to_string(&mut contents)?;
 
-        Ok(serde_yaml::from_str(contents.as_str())?)
+        let mut config: Config = serde_yaml::from_str(contents.as_str())?;
+        config.migrate();
+        config
+            .features
+            .validate()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        Ok(config)
+    }
+
+    // Brings configurations saved by older versions up to date
+    fn migrate(&mut self) {
+        for (_, state) in self.state_graph.node_iter_mut() {
+            state.add_missing_events();
+        }
+        for sign in self.sign_dictionary.signs_mut().values_mut() {
+            sign.add_missing_features();
+        }
     }
 
     pub fn save(&self, path: &str) -> Result<(), Error> {
@@ -140,11 +257,25 @@ impl Default for Config {
 
         Config {
             sign_dictionary: SignDictionary::from(BTreeMap::new()),
+            trajectory_dictionary: TrajectoryDictionary::default(),
             state_graph,
             last_node_id: INITIAL_STATE_INDEX,
 
             sign_switching_smoothness: 0.5f32,
             sign_probability_threshold: 0.9f32,
+
+            features: FeatureConfig::default(),
+            classifier: Classifier::default(),
+
+            primary_hand: None,
+
+            panic_sign: None,
+            swipe: SwipeConfig::default(),
+            push: PushConfig::default(),
+
+            camera: CameraConfig::default(),
+
+            capture: None,
         }
     }
 }
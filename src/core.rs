@@ -1,37 +1,58 @@
-use std::rc::Rc;
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use image::imageops;
+use image::{imageops, RgbImage};
 use nokhwa::ThreadedCamera;
-#[allow(unused_imports)]
-use slint::ComponentHandle;
-use slint::Weak;
-
-use crate::common::state::{StateMachine, StateType};
-use crate::common::{pointer, PointerTracker, ProbabilityVector, Sign};
-use crate::config::INITIAL_STATE_INDEX;
-use crate::mediapipe::Mediapipe;
-use crate::ui::{MainWindow, WindowModel};
+use ordered_float::OrderedFloat;
+
+use crate::common::state::{Observation, StateMachine, StateType};
+use crate::common::{
+    classifier, pointer, Calibration, CalibrationError, CommandExecutor, HandDistance,
+    Measurements, Point2F, PointerTracker, ProbabilityVector, PushDetector, Sign, SignMode,
+    StrokeTracker, SwipeDetector, Template,
+};
+use crate::config::{Capture, INITIAL_STATE_INDEX};
+use crate::hand_tracker::{Hand, HandTracker, Handedness, Packet};
+use crate::observer::Observer;
 use crate::Config;
 
 use super::StateIndex;
 
+// Frame size used when there is no camera, e.g. while replaying a recording
+const BLANK_FRAME_SIZE: (u32, u32) = (640, 480);
+
 pub struct Core {
-    camera: ThreadedCamera,
-    mediapipe: Mediapipe,
-    window: Weak<MainWindow>,
+    camera: Option<ThreadedCamera>,
+    hand_tracker: Box<dyn HandTracker>,
     config: Arc<Mutex<Config>>,
+    observers: Vec<Box<dyn Observer>>,
 
     state_machine: StateMachine<StateIndex>,
     pointer_tracker: PointerTracker,
     probability_vector: ProbabilityVector,
+    // The same for each hand on its own, for two hand signs
+    hand_probabilities: HashMap<Handedness, ProbabilityVector>,
+    swipe_detector: SwipeDetector,
+    push_detector: PushDetector,
+    stroke_tracker: StrokeTracker,
+    // The features and the normalized landmarks of every captured frame
+    sign_samples: Vec<(Sign, Vec<f32>)>,
+    calibration_samples: Vec<Measurements>,
+    calibration: Calibration,
+    // The features of each hand in the last frame, for the hysteresis
+    features: HashMap<Handedness, Sign>,
+    hand_distance: Option<HandDistance>,
 }
 
 impl Core {
     pub fn new(
-        window: Weak<MainWindow>,
-        camera: ThreadedCamera,
+        camera: Option<ThreadedCamera>,
+        hand_tracker: Box<dyn HandTracker>,
+        executor: CommandExecutor,
         config: Arc<Mutex<Config>>,
+        observers: Vec<Box<dyn Observer>>,
     ) -> Self {
         let (num_signs, probability_vector_sensitivity) = {
             let config = Arc::clone(&config);
@@ -46,37 +67,46 @@ impl Core {
         let pointer_tracker = PointerTracker::new(pointer::DEFAULT_WMA_ORDER)
             .expect("ERROR: Unable to initialize pointer tracker.");
         let probability_vector = ProbabilityVector::new(num_signs, probability_vector_sensitivity);
-        let state_machine = StateMachine::new(INITIAL_STATE_INDEX);
+        let state_machine = StateMachine::new(INITIAL_STATE_INDEX, executor);
 
-        let core = Core {
+        let mut core = Core {
             camera,
-            mediapipe: Mediapipe::default(),
-            window,
+            hand_tracker,
             config: Arc::clone(&config),
+            observers,
             state_machine,
             pointer_tracker,
             probability_vector,
+            hand_probabilities: HashMap::new(),
+            swipe_detector: SwipeDetector::default(),
+            push_detector: PushDetector::default(),
+            stroke_tracker: StrokeTracker::default(),
+            sign_samples: Vec::new(),
+            calibration_samples: Vec::new(),
+            calibration: Calibration::default(),
+            features: HashMap::new(),
+            hand_distance: None,
         };
 
-        core.init_window();
+        core.notify_state_changed();
 
         core
     }
 
-    fn init_window(&self) {
-        MainWindow::update_active_node_id(self.window.clone(), INITIAL_STATE_INDEX);
+    pub fn finished(&self) -> bool {
+        self.hand_tracker.finished()
+    }
 
-        let window = self.window.clone();
-        let config = Arc::clone(&self.config);
-        slint::invoke_from_event_loop(move || {
-            let window_model = Rc::new(WindowModel::default());
-            let window = window.unwrap();
+    fn notify_state_changed(&mut self) {
+        let config = self.config.lock().unwrap();
+        let state = config
+            .state_graph()
+            .get_node(self.state_machine.current_state())
+            .expect("ERROR: Invalid state ID");
 
-            window.update_features();
-            window.update_signs(config.clone(), window_model.signs());
-            window.update_state_graph(config.clone(), window_model.clone());
-            window.attach_config_callbacks(config, window_model);
-        });
+        for observer in self.observers.iter_mut() {
+            observer.state_changed(state);
+        }
     }
 
     fn update_pointer_freeze(&mut self) {
@@ -102,80 +132,383 @@ impl Core {
         if num_signs != self.probability_vector.probabilities().len() {
             self.probability_vector =
                 ProbabilityVector::new(num_signs, probability_vector_sensitivity);
+            self.hand_probabilities.clear();
+        }
+    }
+
+    // Samples the hand while a sign capture is requested, the name once it is done
+    fn capture_sign(
+        samples: &mut Vec<(Sign, Vec<f32>)>,
+        config: &mut Config,
+        packet: &Packet,
+    ) -> Option<String> {
+        let (name, frames) = match config.capture {
+            Some(Capture::Sign { ref name, frames }) => (name.clone(), frames),
+            _ => {
+                samples.clear();
+                return None;
+            }
+        };
+
+        if let Some(hand) = packet.hand(config.primary_hand) {
+            samples.push((
+                config.features.sign(&hand.landmarks, hand.handedness, None),
+                classifier::normalize(&hand.landmarks, hand.handedness),
+            ));
         }
+        if samples.len() < frames {
+            return None;
+        }
+
+        let (features, mut examples): (Vec<Sign>, Vec<Vec<f32>>) = samples.drain(..).unzip();
+        let mut sign = Sign::from_samples(&features);
+        config.capture = None;
+
+        // Examples add up over captures of learned signs, for the classifier
+        let signs = config.sign_dictionary_mut().signs_mut();
+        if let Some(existing) = signs.get_mut(&name) {
+            sign.threshold = existing.threshold;
+            sign.smoothness = existing.smoothness;
+            sign.priority = existing.priority;
+            sign.hand = existing.hand;
+            sign.mode = existing.mode;
+            sign.examples = std::mem::take(&mut existing.examples);
+        }
+        if sign.mode == SignMode::Learned {
+            sign.examples.append(&mut examples);
+        }
+        let excess = sign.examples.len().saturating_sub(classifier::MAX_EXAMPLES);
+        sign.examples.drain(..excess);
+        signs.insert(name.clone(), sign);
+
+        Some(name)
+    }
+
+    // Measures the hand while a calibration pose is requested, some once the pose is captured,
+    // an error if the poses do not separate and the calibration starts over
+    fn calibrate(
+        samples: &mut Vec<Measurements>,
+        calibration: &mut Calibration,
+        config: &mut Config,
+        packet: &Packet,
+    ) -> Option<Result<(), CalibrationError>> {
+        let (pose, frames) = match config.capture {
+            Some(Capture::Calibration { pose, frames }) => (pose, frames),
+            _ => {
+                samples.clear();
+                return None;
+            }
+        };
+
+        if let Some(hand) = packet.hand(config.primary_hand) {
+            samples.push(Measurements::new(
+                &hand.landmarks,
+                hand.handedness,
+                config.features.palm_landmarks,
+            ));
+        }
+        if samples.len() < frames {
+            return None;
+        }
+
+        calibration.add(pose, Measurements::mean(samples));
+        samples.clear();
+        config.capture = None;
+
+        match calibration.feature_config(&config.features) {
+            Some(Ok(features)) => config.features = features,
+            Some(Err(e)) => {
+                *calibration = Calibration::default();
+                return Some(Err(e));
+            }
+            None => (),
+        }
+
+        Some(Ok(()))
     }
 
     pub fn tick(&mut self) {
         self.update_pointer_freeze();
         self.check_sign_count_update();
 
-        let config = self.config.lock().unwrap();
-        let mut frame = imageops::flip_horizontal(&self.camera.last_frame());
-        let packet = self.mediapipe.process(&frame);
+        let mut config = self.config.lock().unwrap();
+        let mut frame = match self.camera {
+            Some(ref camera) => imageops::flip_horizontal(&camera.last_frame()),
+            None => RgbImage::new(BLANK_FRAME_SIZE.0, BLANK_FRAME_SIZE.1),
+        };
+        let mut packet = self.hand_tracker.process(&frame);
+        packet.distinct_handedness();
 
         self.pointer_tracker
-            .track(&packet)
+            .track(packet.landmarks(config.primary_hand).as_deref())
             .expect("ERROR: Tracking error.");
 
-        if let Some(ref landmarks) = packet.landmarks {
-            let sign: Sign = landmarks.as_slice().into();
-            let similar = config.sign_dictionary().find_similar(&sign);
+        let landmarks = packet.landmarks(config.primary_hand);
+        let center = landmarks.as_ref().map(|landmarks| {
+            Point2F::new(
+                (landmarks[5].x + landmarks[17].x) / 2f32,
+                (landmarks[5].y + landmarks[17].y) / 2f32,
+            )
+        });
+        let time = Instant::now();
+
+        let followed = match config.trajectory_dictionary().landmark {
+            Some(landmark) => landmarks.and_then(|landmarks| landmarks.get(landmark).copied()),
+            None => center,
+        };
+        // A requested capture takes the stroke instead of the recognizer
+        let mut recorded_template = None;
+        let stroke = match (
+            self.stroke_tracker.track(followed, time),
+            config.capture.clone(),
+        ) {
+            (Some(stroke), Some(Capture::Trajectory(name))) => {
+                config.capture = None;
+                config
+                    .trajectory_dictionary_mut()
+                    .templates_mut()
+                    .insert(name.clone(), Template::from(stroke.as_slice()));
+                recorded_template = Some(name);
+
+                None
+            }
+            (stroke, _) => stroke,
+        };
+
+        let captured_sign = Self::capture_sign(&mut self.sign_samples, &mut config, &packet);
+        let calibrated = Self::calibrate(
+            &mut self.calibration_samples,
+            &mut self.calibration,
+            &mut config,
+            &packet,
+        );
+
+        let mut observation = Observation {
+            sign: None,
+            probability: 0f32,
+            threshold: config.sign_probability_threshold,
+            swipe: self.swipe_detector.track(center, time, &config.swipe),
+            depth_motions: self.push_detector.track(
+                packet
+                    .hand(config.primary_hand)
+                    .and_then(|hand| config.push.depth(&hand.landmarks)),
+                time,
+                &config.push,
+            ),
+            trajectory: stroke
+                .and_then(|stroke| config.trajectory_dictionary().recognize(&stroke))
+                .map(|(name, _)| name.as_str()),
+            hand_signs: HashMap::new(),
+            hand_distance: None,
+            hand_visible: !packet.hands.is_empty(),
+            time,
+        };
+        let mut sign_found = false;
+
+        let mut hands: Vec<&Hand> = packet.hands.iter().collect();
+        hands.sort_by_key(|hand| Reverse(OrderedFloat(hand.confidence)));
+        let measurements: Vec<(Handedness, Measurements)> = hands
+            .iter()
+            .map(|hand| {
+                (
+                    hand.handedness,
+                    Measurements::new(
+                        &hand.landmarks,
+                        hand.handedness,
+                        config.features.palm_landmarks,
+                    ),
+                )
+            })
+            .collect();
+        // The most confident hand comes first and keeps its label
+        let mut features: HashMap<Handedness, Sign> = HashMap::new();
+        for (handedness, measurements) in measurements.iter() {
+            features.entry(*handedness).or_insert_with(|| {
+                config
+                    .features
+                    .sign_from(measurements, self.features.get(handedness))
+            });
+        }
 
-            if let Some(similar) = similar {
-                self.probability_vector.adjust(similar.index);
+        if let (Some(left), Some(right)) = (
+            packet.landmarks(Some(Handedness::Left)),
+            packet.landmarks(Some(Handedness::Right)),
+        ) {
+            observation.hand_distance =
+                config
+                    .features
+                    .hand_distance(&left, &right, self.hand_distance);
+        }
+        self.hand_distance = observation.hand_distance;
 
-                let (probable_sign_index, probability) = self
-                    .probability_vector
-                    .max()
-                    .expect("BUG: Value can't be represented as f32.");
-                let probable_sign = config
+        // How well each sign fits the hand it fits best
+        let min_likelihood = config.features.min_likelihood;
+        let mut likelihoods = vec![0f32; config.sign_dictionary().signs().len()];
+        for (i, (hand, (handedness, measurements))) in
+            hands.iter().zip(measurements.iter()).enumerate()
+        {
+            let hand_likelihoods = config.sign_dictionary().likelihoods(
+                &config
+                    .features
+                    .scores(measurements, self.features.get(handedness)),
+                &classifier::normalize(&hand.landmarks, *handedness),
+                *handedness,
+                &config.classifier,
+            );
+
+            // Two hand triggers look at each hand on its own, smoothed like the overall sign.
+            // The most confident hand comes first and keeps its label.
+            if !hands[..i].iter().any(|seen| seen.handedness == *handedness) {
+                let likely = config
                     .sign_dictionary()
-                    .get_by_index(probable_sign_index)
-                    .expect("BUG: Sign index doesn't exist.");
-
-                if probability > config.sign_probability_threshold
-                    && similar.sign == probable_sign.1
-                {
-                    let state_updated = self.state_machine.process(
-                        config.state_graph(),
-                        probable_sign.0,
-                        &mut self.pointer_tracker,
-                    );
-                    if state_updated {
-                        MainWindow::update_active_node_id(
-                            self.window.clone(),
-                            *self.state_machine.current_state(),
-                        );
+                    .find_likely(&hand_likelihoods, min_likelihood);
+                let smoothness = likely
+                    .as_ref()
+                    .and_then(|likely| likely.sign.smoothness)
+                    .unwrap_or(config.sign_switching_smoothness);
+                let probabilities =
+                    self.hand_probabilities
+                        .entry(*handedness)
+                        .or_insert_with(|| {
+                            ProbabilityVector::new(
+                                likelihoods.len(),
+                                config.sign_switching_smoothness,
+                            )
+                        });
+                probabilities.adjust_soft(
+                    &config
+                        .sign_dictionary()
+                        .evidence(&hand_likelihoods, min_likelihood),
+                    smoothness,
+                );
+
+                if let (Some(likely), Some((index, probability))) = (likely, probabilities.max()) {
+                    let threshold = likely
+                        .sign
+                        .threshold
+                        .unwrap_or(config.sign_probability_threshold);
+
+                    if likely.index == index && probability > threshold {
+                        observation
+                            .hand_signs
+                            .insert(*handedness, likely.name.as_str());
                     }
                 }
-                self.state_machine
-                    .trigger_misc_events(config.state_graph(), &mut self.pointer_tracker);
             }
-        } else {
+            for (likelihood, hand_likelihood) in likelihoods.iter_mut().zip(hand_likelihoods) {
+                *likelihood = likelihood.max(hand_likelihood);
+            }
+        }
+        for (handedness, probabilities) in self.hand_probabilities.iter_mut() {
+            if !measurements.iter().any(|(seen, _)| seen == handedness) {
+                probabilities.rebalance();
+            }
+        }
+        let similar = config
+            .sign_dictionary()
+            .find_likely(&likelihoods, min_likelihood);
+
+        if packet.hands.is_empty() {
             self.probability_vector.rebalance();
+        } else {
+            let smoothness = similar
+                .as_ref()
+                .and_then(|similar| similar.sign.smoothness)
+                .unwrap_or(config.sign_switching_smoothness);
+
+            self.probability_vector.adjust_soft(
+                &config
+                    .sign_dictionary()
+                    .evidence(&likelihoods, min_likelihood),
+                smoothness,
+            );
         }
 
-        slint::invoke_from_event_loop({
-            let window_clone = self.window.clone();
-            let dvb = self.pointer_tracker.dvb().clone();
-            let center = if let Some(ref landmarks) = packet.landmarks {
-                (
-                    (landmarks[5].x + landmarks[17].x) / 2f32,
-                    (landmarks[5].y + landmarks[17].y) / 2f32,
-                )
-            } else {
-                (0f32, 0f32)
-            };
+        if let Some(similar) = similar {
+            sign_found = true;
 
-            move || {
-                let window = window_clone.unwrap();
+            let (probable_sign_index, probability) = self
+                .probability_vector
+                .max()
+                .expect("BUG: Value can't be represented as f32.");
+            let probable_sign = config
+                .sign_dictionary()
+                .get_by_index(probable_sign_index)
+                .expect("BUG: Sign index doesn't exist.");
 
-                let camera_visible = window.get_active_page() == 0;
-                if camera_visible {
-                    let image = PointerTracker::annotate(&mut frame, dvb, center);
-                    window.set_webcam_image(image);
+            if similar.index == probable_sign_index {
+                observation.sign = Some(probable_sign.0.as_str());
+                observation.probability = probability;
+                if let Some(threshold) = probable_sign.1.threshold {
+                    observation.threshold = threshold;
                 }
             }
-        });
+        }
+
+        self.features = features;
+
+        let recognized_sign = observation.recognized(None);
+        self.state_machine.executor_mut().set_sign(recognized_sign);
+
+        let panic = recognized_sign.is_some() && recognized_sign == config.panic_sign.as_deref();
+        // Time based edges may fire without any sign
+        let state_updated = if panic {
+            self.state_machine
+                .reset(INITIAL_STATE_INDEX, &mut self.pointer_tracker)
+        } else {
+            self.state_machine.process(
+                config.state_graph(),
+                &observation,
+                &mut self.pointer_tracker,
+            )
+        };
+        if state_updated {
+            let state = config
+                .state_graph()
+                .get_node(self.state_machine.current_state())
+                .expect("ERROR: Invalid state ID");
+
+            for observer in self.observers.iter_mut() {
+                observer.state_changed(state);
+            }
+        }
+
+        if sign_found {
+            self.state_machine
+                .trigger_misc_events(config.state_graph(), &mut self.pointer_tracker);
+        }
+
+        let center = center.unwrap_or_default();
+        PointerTracker::annotate(
+            &mut frame,
+            self.pointer_tracker.dvb().clone(),
+            (center.x, center.y),
+        );
+
+        let command_log = self.state_machine.executor_mut().drain_log();
+        for observer in self.observers.iter_mut() {
+            observer.frame_processed(&frame);
+
+            for log in command_log.iter() {
+                observer.command_logged(log);
+            }
+            if let Some(ref name) = recorded_template {
+                observer.template_recorded(name);
+            }
+            if let Some(ref name) = captured_sign {
+                let sign = config
+                    .sign_dictionary()
+                    .signs()
+                    .get(name)
+                    .expect("BUG: Captured sign doesn't exist.");
+
+                observer.sign_captured(name, sign);
+            }
+            match calibrated {
+                Some(Ok(())) => observer.calibration_captured(self.calibration.next_pose()),
+                Some(Err(ref e)) => observer.calibration_failed(e),
+                None => (),
+            }
+        }
     }
 }
@@ -1,15 +1,30 @@
 use std::thread;
 use std::time::Duration;
 
+use std::time::Instant;
+
+use clap::Parser;
 use image::imageops;
 use nokhwa::{CameraFormat, FrameFormat, ThreadedCamera};
 use plotters::coord::types::RangedCoordf32;
 use plotters::prelude::*;
 
 use anthon_rs::common::PointerTracker;
-use anthon_rs::mediapipe::{Mediapipe, Packet};
+use anthon_rs::hand_tracker::{self, HandTracker, Packet};
+use anthon_rs::recording::Recording;
+
+#[derive(Parser)]
+struct Args {
+    /// Plot the packets of this recording instead of recording new ones
+    #[clap(long, value_name = "PATH")]
+    replay: Option<String>,
+
+    /// Save the recorded packets to this file
+    #[clap(long, value_name = "PATH")]
+    record: Option<String>,
+}
 
-fn record(count: usize) -> Vec<Packet> {
+fn record(hand_tracker: &mut dyn HandTracker, count: usize) -> Recording {
     let mut camera = ThreadedCamera::new(
         0,
         Some(CameraFormat::new_from(
@@ -20,24 +35,26 @@ fn record(count: usize) -> Vec<Packet> {
         )),
     )
     .expect("Capturing device initialization failed.");
-    let mediapipe = Mediapipe::default();
 
     if camera.open_stream(|_| ()).is_err() {
         panic!("Opening capture stream failed.");
     }
 
-    (0..count)
-        .map(|_| loop {
-            let frame = imageops::flip_horizontal(&camera.last_frame());
-            let packet = mediapipe.process(&frame);
+    let mut recording = Recording::default();
+    let started = Instant::now();
 
-            if packet.landmarks.is_some() {
-                break packet;
-            } else {
-                thread::sleep(Duration::from_millis(anthon_rs::MPF));
-            }
-        })
-        .collect()
+    while recording.frames().len() < count {
+        let frame = imageops::flip_horizontal(&camera.last_frame());
+        let packet = hand_tracker.process(&frame);
+
+        if !packet.hands.is_empty() {
+            recording.push(started.elapsed().as_millis() as u64, &packet);
+        } else {
+            thread::sleep(Duration::from_millis(anthon_rs::MPF));
+        }
+    }
+
+    recording
 }
 
 fn filter(packets: &[Packet], wma_order: usize) -> Vec<(f32, f32)> {
@@ -48,7 +65,7 @@ fn filter(packets: &[Packet], wma_order: usize) -> Vec<(f32, f32)> {
         .enumerate()
         .map(|(i, packet)| {
             let t = i as f32 / packets.len() as f32;
-            pointer.track(packet).unwrap();
+            pointer.track(packet.landmarks(None).as_deref()).unwrap();
 
             (t, pointer.x())
         })
@@ -81,14 +98,28 @@ where
 }
 
 fn main() {
-    let packets = record(100);
+    let args = Args::parse();
+    let recording = match args.replay {
+        Some(ref path) => Recording::from_file(path).expect("Unable to load the recording."),
+        None => {
+            let mut hand_tracker = hand_tracker::default_tracker()
+                .expect("No hand tracking backend available. Build with the `mediapipe` feature.");
+            record(hand_tracker.as_mut(), 100)
+        }
+    };
+
+    if let Some(ref path) = args.record {
+        recording.save(path).expect("Unable to save the recording.");
+    }
+
+    let packets = recording.packets();
     let real: Vec<(f32, f32)> = packets
         .iter()
         .enumerate()
         .map(|(i, packet)| {
             let t = i as f32 / packets.len() as f32;
 
-            let x = if let Some(ref landmarks) = packet.landmarks {
+            let x = if let Some(landmarks) = packet.landmarks(None) {
                 (landmarks[5].x + landmarks[17].x).abs() / 2f32
             } else {
                 0f32
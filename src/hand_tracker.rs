@@ -0,0 +1,111 @@
+use image::RgbImage;
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+
+pub use crate::common::Handedness;
+use crate::common::{Point2F, Point3F};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hand {
+    pub landmarks: Vec<Point3F>,
+    pub handedness: Handedness,
+    // Detection confidence, from 0 to 1
+    pub confidence: f32,
+}
+
+impl Hand {
+    // The landmarks on the frame, without their depth
+    pub fn landmarks_2d(&self) -> Vec<Point2F> {
+        self.landmarks.iter().copied().map(Point2F::from).collect()
+    }
+}
+
+#[derive(Default)]
+pub struct Packet {
+    // Up to two hands
+    pub hands: Vec<Hand>,
+}
+
+impl Packet {
+    // The hand with the given label, or the most confident one if none is given
+    pub fn hand(&self, handedness: Option<Handedness>) -> Option<&Hand> {
+        match handedness {
+            Some(handedness) => self.hands.iter().find(|hand| hand.handedness == handedness),
+            None => self
+                .hands
+                .iter()
+                .max_by_key(|hand| OrderedFloat(hand.confidence)),
+        }
+    }
+
+    pub fn landmarks(&self, handedness: Option<Handedness>) -> Option<Vec<Point2F>> {
+        self.hand(handedness).map(Hand::landmarks_2d)
+    }
+
+    // Two hands are sometimes given the same label, the less confident one is the other hand
+    pub fn distinct_handedness(&mut self) {
+        if let [first, second] = self.hands.as_mut_slice() {
+            if first.handedness == second.handedness {
+                let less_confident = if first.confidence < second.confidence {
+                    first
+                } else {
+                    second
+                };
+
+                less_confident.handedness = match less_confident.handedness {
+                    Handedness::Left => Handedness::Right,
+                    Handedness::Right => Handedness::Left,
+                };
+            }
+        }
+    }
+}
+
+pub trait HandTracker {
+    fn process(&mut self, frame: &RgbImage) -> Packet;
+
+    // Whether the tracker ran out of packets, e.g. a replay reached its end
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+// The tracker compiled into this build, if any
+pub fn default_tracker() -> Option<Box<dyn HandTracker>> {
+    #[cfg(feature = "mediapipe")]
+    {
+        Some(Box::new(crate::mediapipe::Mediapipe::default()))
+    }
+
+    #[cfg(not(feature = "mediapipe"))]
+    {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hand_tracker::{Hand, Handedness, Packet};
+
+    fn hand(handedness: Handedness, confidence: f32) -> Hand {
+        Hand {
+            landmarks: Vec::new(),
+            handedness,
+            confidence,
+        }
+    }
+
+    #[test]
+    fn distinct_handedness() {
+        let mut packet = Packet {
+            hands: vec![hand(Handedness::Right, 0.6), hand(Handedness::Right, 0.9)],
+        };
+        packet.distinct_handedness();
+
+        assert_eq!(packet.hands[0].handedness, Handedness::Left);
+        assert_eq!(packet.hands[1].handedness, Handedness::Right);
+
+        packet.distinct_handedness();
+        assert_eq!(packet.hands[0].handedness, Handedness::Left);
+    }
+}
@@ -1,85 +1,258 @@
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::SystemTime;
+use std::time::{Duration, Instant};
 
-use nokhwa::{CameraFormat, FrameFormat, ThreadedCamera};
+use clap::Parser;
+use nokhwa::{CameraFormat, ThreadedCamera};
 use slint::ComponentHandle;
 
-use config::Config;
-use ui::MainWindow;
+use cli::{Action, Args};
+use config::{CameraConfig, Config};
+use observer::{FileLogObserver, LogObserver, Observer};
+use ui::{MainWindow, WindowObserver};
 
 use crate::common::filter::Wmaf32;
-use crate::common::{state::ConditionalEdge, Graph, State};
+use crate::common::state::{ConditionalEdge, Trigger};
+use crate::common::{CommandExecutor, Graph, SignMode, State};
 use crate::core::Core;
+use crate::hand_tracker::HandTracker;
+use crate::recording::{Recording, RecordingTracker, ReplayTracker};
 
+mod cli;
 pub mod common;
 mod config;
 mod core;
+pub mod hand_tracker;
+#[cfg(feature = "mediapipe")]
 pub mod mediapipe;
+mod observer;
+pub mod recording;
 mod ui;
 
 type StateIndex = i32;
 type ConditionalGraph =
-    Graph<StateIndex, State<StateIndex>, ConditionalEdge<StateIndex, Option<String>>>;
+    Graph<StateIndex, State<StateIndex>, ConditionalEdge<StateIndex, Option<Trigger>>>;
 
 pub const FPS: u64 = 30;
 pub const MPF: u64 = ((1f32 / FPS as f32) * 1000f32) as u64;
-const CONFIG_PATH: &str = "config.yaml";
+
+// Set by SIGINT and SIGTERM while running headless
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    if let Some(Action::Validate) = args.action {
+        return validate(&args);
+    }
+
+    let replay = match args.replay {
+        Some(ref path) => Some(Recording::from_file(path)?),
+        None => None,
+    };
+
+    if args.headless {
+        run_headless(args, replay)
+    } else {
+        run_window(args, replay)
+    }
+}
+
+fn run_window(args: Args, replay: Option<Recording>) -> Result<(), Box<dyn std::error::Error>> {
     let interrupted = Arc::new(Mutex::new(false));
-    let config = Arc::new(Mutex::new(
-        Config::from_file(CONFIG_PATH).unwrap_or_default(),
-    ));
+    // Start from scratch only without a config file, saving on exit would overwrite a broken one
+    let config = match Config::from_file(&args.config) {
+        Ok(config) => config,
+        Err(config::Error::IOError(e)) if e.kind() == ErrorKind::NotFound => Config::default(),
+        Err(e) => return Err(format!("Unable to load {}: {}", args.config, e).into()),
+    };
+    let config = Arc::new(Mutex::new(config));
 
     let window = MainWindow::new();
-    let mut camera = ThreadedCamera::new(
-        0,
-        Some(CameraFormat::new_from(
-            640,
-            480,
-            FrameFormat::MJPEG,
-            crate::FPS as u32,
-        )),
-    )
-    .expect("Capturing device initialization failed.");
+    window.init(Arc::clone(&config));
 
-    camera
-        .open_stream(|_| ())
-        .expect("Opening capture stream failed.");
+    let camera_config = args.camera_config(config.lock().unwrap().camera.clone());
+    let fps = camera_config.fps;
+    let camera = if replay.is_none() {
+        Some(open_camera(camera_config))
+    } else {
+        None
+    };
 
-    let window_weak = window.as_weak();
+    let mut observers: Vec<Box<dyn Observer>> = vec![Box::new(WindowObserver::new(
+        window.as_weak(),
+        Arc::clone(&config),
+    ))];
+    observers.extend(file_log_observer(&args));
     let config_clone = Arc::clone(&config);
+    let config_path = args.config.clone();
     let processing_thread = thread::spawn({
         let interrupted = interrupted.clone();
 
         move || {
-            let mut core = Core::new(window_weak, camera, config_clone);
+            let hand_tracker = create_hand_tracker(&args, replay);
+            let executor = CommandExecutor::new(args.dry_run);
+            let mut core = Core::new(camera, hand_tracker, executor, config_clone, observers);
             let mut spf = Wmaf32::new(5);
 
             while !*interrupted.lock().unwrap() {
-                let last_time = SystemTime::now();
+                let started = Instant::now();
                 core.tick();
-                // thread::sleep(Duration::from_millis(MPF));
-
-                spf.set_value(
-                    SystemTime::now()
-                        .duration_since(last_time)
-                        .unwrap()
-                        .as_secs_f32(),
-                );
+                pace(started, fps);
+
+                spf.set_value(started.elapsed().as_secs_f32());
                 println!("{}", 1f32 / *spf);
-                thread::yield_now()
             }
         }
     });
 
     window.run();
     *interrupted.lock().unwrap() = true;
-    config.lock().unwrap().save(CONFIG_PATH)?;
+    config.lock().unwrap().save(&config_path)?;
     processing_thread
         .join()
         .expect("Failed to shutdown the processing thread");
 
     Ok(())
 }
+
+// Runs until the hand tracker is finished or the process is interrupted
+fn run_headless(args: Args, replay: Option<Recording>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::from_file(&args.config)?;
+    let camera_config = args.camera_config(config.camera.clone());
+    let fps = camera_config.fps;
+    let camera = if replay.is_none() {
+        Some(open_camera(camera_config))
+    } else {
+        None
+    };
+
+    let hand_tracker = create_hand_tracker(&args, replay);
+    let executor = CommandExecutor::new(args.dry_run);
+    let mut observers: Vec<Box<dyn Observer>> = vec![Box::new(LogObserver)];
+    observers.extend(file_log_observer(&args));
+    let config = Arc::new(Mutex::new(config));
+    let mut core = Core::new(camera, hand_tracker, executor, config, observers);
+
+    handle_interrupts();
+    while !core.finished() && !INTERRUPTED.load(Ordering::SeqCst) {
+        let started = Instant::now();
+        core.tick();
+        pace(started, fps);
+    }
+
+    // Dropping the core also saves the recording, if any
+    drop(core);
+
+    Ok(())
+}
+
+extern "C" fn interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+fn handle_interrupts() {
+    let handler = interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+// Sleeps for the rest of the frame, so the loop runs at the given frames per second
+fn pace(started: Instant, fps: u32) {
+    let frame = Duration::from_secs_f32(1f32 / fps.max(1) as f32);
+
+    if let Some(rest) = frame.checked_sub(started.elapsed()) {
+        thread::sleep(rest);
+    }
+}
+
+// Fails on conflicts the sign priorities leave to the name order
+fn validate(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::from_file(&args.config)?;
+    let dictionary = config.sign_dictionary();
+    let conflicts = dictionary.conflicts();
+
+    for conflict in conflicts.iter() {
+        println!("{}", conflict);
+    }
+    for name in dictionary.untrained() {
+        println!(
+            "{} is learned but has no examples yet, it is never recognized",
+            name
+        );
+    }
+    let learned = dictionary
+        .signs()
+        .values()
+        .filter(|sign| sign.mode == SignMode::Learned)
+        .count();
+    if learned > 0 {
+        println!(
+            "{} learned signs are not checked, they have no features to compare",
+            learned
+        );
+    }
+
+    // Others are told apart by how well the hand fits them
+    let unresolved = conflicts
+        .iter()
+        .filter(|c| c.identical && c.winner.is_none())
+        .count();
+    if unresolved > 0 {
+        return Err(format!("{} unresolved sign conflicts", unresolved).into());
+    }
+    println!("No unresolved sign conflicts");
+
+    Ok(())
+}
+
+// Must be called from the processing thread, native trackers are not Send
+fn create_hand_tracker(args: &Args, replay: Option<Recording>) -> Box<dyn HandTracker> {
+    let hand_tracker: Box<dyn HandTracker> = match replay {
+        Some(recording) => Box::new(ReplayTracker::new(recording, args.replay_speed())),
+        None => hand_tracker::default_tracker()
+            .expect("No hand tracking backend available. Build with the `mediapipe` feature."),
+    };
+
+    match args.record {
+        Some(ref path) => Box::new(RecordingTracker::new(hand_tracker, path.clone())),
+        None => hand_tracker,
+    }
+}
+
+fn file_log_observer(args: &Args) -> Option<Box<dyn Observer>> {
+    match FileLogObserver::new(&args.command_log) {
+        Ok(observer) => Some(Box::new(observer)),
+        Err(e) => {
+            eprintln!(
+                "ERROR: Unable to open the command log {}: {}",
+                args.command_log, e
+            );
+            None
+        }
+    }
+}
+
+fn open_camera(camera: CameraConfig) -> ThreadedCamera {
+    let mut camera = ThreadedCamera::new(
+        camera.index,
+        Some(CameraFormat::new_from(
+            camera.width,
+            camera.height,
+            camera.format.into(),
+            camera.fps,
+        )),
+    )
+    .expect("Capturing device initialization failed.");
+
+    camera
+        .open_stream(|_| ())
+        .expect("Opening capture stream failed.");
+
+    camera
+}
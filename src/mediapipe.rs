@@ -1,7 +1,8 @@
-use image::{ImageBuffer, Rgb};
-use libc::{c_void, size_t};
+use image::RgbImage;
+use libc::{c_int, c_void, size_t};
 
-use crate::common::Point2F;
+use crate::common::{Point3F, HAND_LANDMARKS};
+use crate::hand_tracker::{Hand, HandTracker, Handedness, Packet};
 
 #[link(name = "mediapipe")]
 extern "C" {
@@ -15,24 +16,49 @@ extern "C" {
     ) -> *mut FFIPacket;
 }
 
+// The most hands the library is set up to track, more means it does not match these bindings
+const MAX_HANDS: usize = 2;
+
+#[repr(C)]
+struct FFIHand {
+    landmarks_len: size_t,
+    landmarks: *mut Point3F,
+    // 0 for the left hand, 1 for the right one
+    handedness: c_int,
+    confidence: f32,
+}
+
 #[repr(C)]
 struct FFIPacket {
     // image_len: size_t,
     // image: *const u8,
-    landmarks_len: size_t,
-    landmarks: *mut Point2F,
+    hands_len: size_t,
+    hands: *mut FFIHand,
 }
 
-pub struct Packet {
-    pub landmarks: Option<Vec<Point2F>>,
+impl From<FFIHand> for Hand {
+    fn from(hand: FFIHand) -> Self {
+        let landmarks =
+            unsafe { Vec::from_raw_parts(hand.landmarks, hand.landmarks_len, hand.landmarks_len) };
+
+        Hand {
+            landmarks,
+            handedness: if hand.handedness == 0 {
+                Handedness::Left
+            } else {
+                Handedness::Right
+            },
+            confidence: hand.confidence,
+        }
+    }
 }
 
 pub struct Mediapipe {
     ptr: *const c_void,
 }
 
-impl Mediapipe {
-    pub fn process(&self, frame: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Packet {
+impl HandTracker for Mediapipe {
+    fn process(&mut self, frame: &RgbImage) -> Packet {
         let width = frame.width();
         let height = frame.height();
         let data = frame.as_raw();
@@ -46,17 +72,36 @@ impl Mediapipe {
                 height as size_t,
             ));
             // let image_slice = std::slice::from_raw_parts(ffi_packet.image, ffi_packet.image_len);
-            let landmarks = if ffi_packet.landmarks_len > 0 {
-                Some(Vec::from_raw_parts(
-                    ffi_packet.landmarks,
-                    ffi_packet.landmarks_len,
-                    ffi_packet.landmarks_len,
-                ))
+            // Anything unexpected is leaked rather than read with the wrong layout
+            let hands = if ffi_packet.hands_len > MAX_HANDS {
+                eprintln!(
+                    "ERROR: Mediapipe returned {} hands, the library does not match",
+                    ffi_packet.hands_len
+                );
+                Vec::new()
+            } else if ffi_packet.hands_len > 0 {
+                Vec::from_raw_parts(ffi_packet.hands, ffi_packet.hands_len, ffi_packet.hands_len)
             } else {
-                None
+                Vec::new()
             };
 
-            Packet { landmarks }
+            Packet {
+                hands: hands
+                    .into_iter()
+                    .filter(|hand| match hand.landmarks_len {
+                        0 => false,
+                        HAND_LANDMARKS => true,
+                        len => {
+                            eprintln!(
+                                "ERROR: Mediapipe returned {} landmarks, the library does not match",
+                                len
+                            );
+                            false
+                        }
+                    })
+                    .map(Hand::from)
+                    .collect(),
+            }
         }
     }
 }
@@ -0,0 +1,74 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use image::RgbImage;
+
+use crate::common::{CalibrationError, CalibrationPose, CommandLog, Sign, State};
+use crate::StateIndex;
+
+// Receives what the core recognizes, whether or not a window is attached
+pub trait Observer: Send {
+    fn state_changed(&mut self, _state: &State<StateIndex>) {}
+
+    // The camera frame, annotated with the tracking overlay
+    fn frame_processed(&mut self, _frame: &RgbImage) {}
+
+    // An executed program finished, failed to start or timed out
+    fn command_logged(&mut self, _log: &CommandLog) {}
+
+    // A requested trajectory capture was stored as a template
+    fn template_recorded(&mut self, _name: &str) {}
+
+    // A requested sign capture was added to the dictionary
+    fn sign_captured(&mut self, _name: &str, _sign: &Sign) {}
+
+    // A requested calibration pose was captured, None once the thresholds are set
+    fn calibration_captured(&mut self, _next: Option<CalibrationPose>) {}
+
+    // The captured poses were rejected, the calibration starts over from the first pose
+    fn calibration_failed(&mut self, _error: &CalibrationError) {}
+}
+
+// Used when running headless
+pub struct LogObserver;
+
+impl Observer for LogObserver {
+    fn state_changed(&mut self, state: &State<StateIndex>) {
+        println!("State: {}", state.name);
+    }
+
+    fn command_logged(&mut self, log: &CommandLog) {
+        println!("Command: {}", log);
+    }
+}
+
+// Appends the command log to a file, including the full error output
+pub struct FileLogObserver {
+    file: File,
+}
+
+impl FileLogObserver {
+    pub fn new(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(FileLogObserver { file })
+    }
+}
+
+impl Observer for FileLogObserver {
+    fn command_logged(&mut self, log: &CommandLog) {
+        let time = log
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+
+        writeln!(self.file, "[{}] {}", time, log).ok();
+        if log.failed() && !log.stderr.trim().is_empty() {
+            for line in log.stderr.trim_end().lines() {
+                writeln!(self.file, "    {}", line).ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use std::fs::{File, OpenOptions};
+use std::io::prelude::Read;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{Point3F, HAND_LANDMARKS};
+use crate::config::Error;
+use crate::hand_tracker::{Hand, HandTracker, Handedness, Packet};
+
+// How often a recording in progress is saved, so a killed process loses little of it
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedFrame {
+    // Milliseconds since the start of the recording
+    pub timestamp: u64,
+    #[serde(default)]
+    pub hands: Vec<Hand>,
+    // Recordings made before the handedness was tracked have a single unlabeled hand
+    #[serde(default, skip_serializing)]
+    landmarks: Option<Vec<Point3F>>,
+}
+
+impl From<&RecordedFrame> for Packet {
+    fn from(frame: &RecordedFrame) -> Self {
+        let legacy = frame.landmarks.clone().map(|landmarks| Hand {
+            landmarks,
+            handedness: Handedness::Right,
+            confidence: 1f32,
+        });
+
+        Packet {
+            hands: frame.hands.iter().cloned().chain(legacy).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Recording {
+    frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn frames(&self) -> &[RecordedFrame] {
+        self.frames.as_ref()
+    }
+
+    pub fn push(&mut self, timestamp: u64, packet: &Packet) {
+        self.frames.push(RecordedFrame {
+            timestamp,
+            hands: packet.hands.clone(),
+            landmarks: None,
+        });
+    }
+
+    pub fn packets(&self) -> Vec<Packet> {
+        self.frames.iter().map(Packet::from).collect()
+    }
+
+    pub fn from_file(path: &str) -> Result<Recording, Error> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+
+        file.read_to_string(&mut contents)?;
+
+        let recording: Recording = serde_yaml::from_str(contents.as_str())?;
+        recording.validate()?;
+
+        Ok(recording)
+    }
+
+    // Every hand needs all its landmarks, the core indexes them directly
+    fn validate(&self) -> std::io::Result<()> {
+        for frame in self.frames.iter() {
+            let hands = frame.hands.iter().map(|hand| &hand.landmarks);
+
+            if let Some(landmarks) = hands
+                .chain(frame.landmarks.as_ref())
+                .find(|landmarks| landmarks.len() != HAND_LANDMARKS)
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "The hand recorded at {} ms has {} landmarks instead of {}",
+                        frame.timestamp,
+                        landmarks.len(),
+                        HAND_LANDMARKS
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let mut file = File::create(path)?;
+        file.write_all(serde_yaml::to_string(self)?.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReplaySpeed {
+    // Follow the recorded timestamps, multiplied by a factor
+    Timed(f32),
+    // One recorded frame per processed frame, as fast as the caller ticks
+    Stepped,
+}
+
+pub struct ReplayTracker {
+    recording: Recording,
+    speed: ReplaySpeed,
+    started: Option<Instant>,
+    position: usize,
+}
+
+impl ReplayTracker {
+    pub fn new(recording: Recording, speed: ReplaySpeed) -> Self {
+        ReplayTracker {
+            recording,
+            speed,
+            started: None,
+            position: 0,
+        }
+    }
+
+    // Move to the last frame recorded at or before the elapsed time
+    fn seek(&mut self, elapsed: u64) {
+        let frames = self.recording.frames();
+
+        while self.position < frames.len() && frames[self.position].timestamp <= elapsed {
+            self.position += 1;
+        }
+    }
+
+    fn next_frame(&mut self) -> Option<&RecordedFrame> {
+        match self.speed {
+            ReplaySpeed::Stepped => {
+                self.position += 1;
+            }
+            ReplaySpeed::Timed(factor) => {
+                let started = *self.started.get_or_insert_with(Instant::now);
+                let elapsed = started.elapsed().as_secs_f32() * factor * 1000f32;
+
+                self.seek(elapsed as u64);
+            }
+        }
+
+        // The current frame is the last one passed, none before the first timestamp
+        self.recording.frames().get(self.position.checked_sub(1)?)
+    }
+}
+
+impl HandTracker for ReplayTracker {
+    fn process(&mut self, _frame: &RgbImage) -> Packet {
+        if self.finished() {
+            return Packet::default();
+        }
+
+        match self.next_frame() {
+            Some(frame) => frame.into(),
+            None => Packet::default(),
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.position >= self.recording.frames().len()
+    }
+}
+
+// Wraps another tracker and saves everything it sees, regularly and once dropped
+pub struct RecordingTracker {
+    inner: Box<dyn HandTracker>,
+    // The frames seen since the last save
+    recording: Recording,
+    created: bool,
+    started: Instant,
+    last_saved: Instant,
+    path: String,
+}
+
+impl RecordingTracker {
+    pub fn new(inner: Box<dyn HandTracker>, path: String) -> Self {
+        RecordingTracker {
+            inner,
+            recording: Recording::default(),
+            created: false,
+            started: Instant::now(),
+            last_saved: Instant::now(),
+            path,
+        }
+    }
+
+    fn save(&mut self) {
+        self.last_saved = Instant::now();
+
+        if let Err(e) = self.append() {
+            eprintln!(
+                "ERROR: Unable to save the recording to {}: {}",
+                self.path, e
+            );
+        }
+    }
+
+    // Only the new frames are written, an interrupted write can't lose the saved ones
+    fn append(&mut self) -> Result<(), Error> {
+        let frames = &self.recording.frames;
+        let mut yaml = String::new();
+
+        if !self.created {
+            yaml.push_str(if frames.is_empty() {
+                "frames: []\n"
+            } else {
+                "frames:\n"
+            });
+        }
+        if !frames.is_empty() {
+            yaml.push_str(serde_yaml::to_string(frames)?.trim_start_matches("---\n"));
+            if !yaml.ends_with('\n') {
+                yaml.push('\n');
+            }
+        }
+        if yaml.is_empty() {
+            return Ok(());
+        }
+
+        let mut file = if self.created {
+            OpenOptions::new().append(true).open(&self.path)?
+        } else {
+            File::create(&self.path)?
+        };
+        file.write_all(yaml.as_bytes())?;
+
+        self.created = true;
+        self.recording.frames.clear();
+
+        Ok(())
+    }
+}
+
+impl HandTracker for RecordingTracker {
+    fn process(&mut self, frame: &RgbImage) -> Packet {
+        let packet = self.inner.process(frame);
+        let timestamp = self.started.elapsed().as_millis() as u64;
+        self.recording.push(timestamp, &packet);
+        if self.last_saved.elapsed() >= SAVE_INTERVAL {
+            self.save();
+        }
+
+        packet
+    }
+
+    fn finished(&self) -> bool {
+        self.inner.finished()
+    }
+}
+
+impl Drop for RecordingTracker {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbImage;
+
+    use crate::common::Point3F;
+    use crate::hand_tracker::{Hand, HandTracker, Handedness, Packet};
+    use crate::recording::{Recording, RecordingTracker, ReplaySpeed, ReplayTracker};
+
+    fn recording() -> Recording {
+        let mut recording = Recording::default();
+        let landmarks = (0..21)
+            .map(|i| Point3F::new(i as f32, 0f32, -0.1f32))
+            .collect();
+
+        recording.push(0, &Packet::default());
+        recording.push(
+            33,
+            &Packet {
+                hands: vec![Hand {
+                    landmarks,
+                    handedness: Handedness::Left,
+                    confidence: 0.9f32,
+                }],
+            },
+        );
+        recording.push(66, &Packet::default());
+
+        recording
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        let yaml = serde_yaml::to_string(&recording()).unwrap();
+        let recording: Recording = serde_yaml::from_str(&yaml).unwrap();
+
+        assert_eq!(recording.frames().len(), 3);
+        assert_eq!(recording.frames()[1].timestamp, 33);
+        assert_eq!(recording.frames()[1].hands[0].landmarks[20].x, 20f32);
+        assert_eq!(recording.frames()[1].hands[0].landmarks[20].z, -0.1f32);
+        assert_eq!(recording.frames()[1].hands[0].handedness, Handedness::Left);
+    }
+
+    #[test]
+    fn unlabeled_recordings() {
+        let landmarks = vec!["{x: 0.5, y: 0.5}"; 21].join(", ");
+        let yaml = format!(
+            "frames:\n  - timestamp: 0\n    landmarks: [{}]\n  - timestamp: 33\n    landmarks: ~",
+            landmarks
+        );
+        let recording: Recording = serde_yaml::from_str(&yaml).unwrap();
+        let packets = recording.packets();
+
+        assert!(recording.validate().is_ok());
+        assert_eq!(packets[0].hand(None).unwrap().landmarks[0].x, 0.5f32);
+        assert_eq!(packets[0].hand(None).unwrap().landmarks[0].z, 0f32);
+        assert!(packets[1].hands.is_empty());
+    }
+
+    #[test]
+    fn missing_landmarks() {
+        let legacy = "frames:\n  - timestamp: 0\n    landmarks: [{x: 0.5, y: 0.5}]";
+        let labeled = "frames:\n  - timestamp: 0\n    hands:\n      - landmarks: [{x: 0.5, y: 0.5}]\n        handedness: Left\n        confidence: 0.9";
+
+        for yaml in [legacy, labeled] {
+            let recording: Recording = serde_yaml::from_str(yaml).unwrap();
+
+            assert!(recording.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn saved_in_parts() {
+        let path = std::env::temp_dir().join(format!("recording-{}.yaml", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let replay = ReplayTracker::new(recording(), ReplaySpeed::Stepped);
+        let mut tracker = RecordingTracker::new(Box::new(replay), path.clone());
+        let frame = RgbImage::new(1, 1);
+
+        tracker.process(&frame);
+        tracker.process(&frame);
+        tracker.save();
+        tracker.process(&frame);
+        tracker.save();
+        tracker.save();
+        drop(tracker);
+
+        let saved = Recording::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved.frames().len(), 3);
+        assert_eq!(
+            saved.packets()[1].hand(None).unwrap().landmarks[20].x,
+            20f32
+        );
+        assert!(saved.packets()[2].hands.is_empty());
+    }
+
+    #[test]
+    fn stepped_replay() {
+        let mut tracker = ReplayTracker::new(recording(), ReplaySpeed::Stepped);
+        let frame = RgbImage::new(1, 1);
+
+        assert!(tracker.process(&frame).hands.is_empty());
+        assert!(tracker.process(&frame).hand(None).is_some());
+        assert!(!tracker.finished());
+        assert!(tracker.process(&frame).hands.is_empty());
+        assert!(tracker.finished());
+    }
+
+    #[test]
+    fn timed_seek() {
+        let mut tracker = ReplayTracker::new(recording(), ReplaySpeed::Timed(1f32));
+
+        tracker.seek(40);
+        assert_eq!(tracker.position, 2);
+        assert!(!tracker.finished());
+
+        tracker.seek(1000);
+        assert!(tracker.finished());
+    }
+}
@@ -2,23 +2,34 @@ use std::rc::Rc;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
-use slint::{Model, SharedString, VecModel, Weak};
+use image::RgbImage;
+use slint::{Image, Model, Rgb8Pixel, SharedPixelBuffer, SharedString, VecModel, Weak};
 use strum::{IntoEnumIterator, VariantNames};
 
 use slint_generatedMainWindow::{
     Command as SlintCommand, Edge as SlintEdge, Node as SlintNode, Sign as SlintSign,
 };
 
-use crate::common::state::{StateEvent, StateType};
+use crate::common::state::{
+    Hold, HoldDiscriminants, StateEvent, StateType, Trigger, TriggerDiscriminants,
+};
 use crate::common::{
-    Axis, Command as CoreCommand, CommandDiscriminants, Edge as CoreEdge, Feature, KeyEvent,
-    MouseButton, Node as CoreNode, ScrollCommand, Sign as CoreSign,
+    escape_text, steps_from_str, steps_to_string, unescape_text, Axis, CalibrationError,
+    CalibrationPose, Command as CoreCommand, CommandDiscriminants, CommandLog, DepthMotion,
+    Direction, Edge as CoreEdge, ExecCommand, Feature, HandDistance, Handedness, Key, KeyEvent,
+    MouseButton, Node as CoreNode, ScrollCommand, Shortcut, Sign as CoreSign, SignMode,
 };
-use crate::config::INITIAL_STATE_INDEX;
+use crate::config::{Capture, CALIBRATION_FRAMES, INITIAL_STATE_INDEX, SIGN_CAPTURE_FRAMES};
+use crate::observer::Observer;
 use crate::{ConditionalEdge, ConditionalGraph, Config, State, StateIndex};
 
 slint::include_modules!();
 
+// Hold type of the edges firing right away
+const NO_HOLD: &str = "None";
+// Hand of the signs recognized on either hand
+const ANY_HAND: &str = "Any";
+
 pub struct WindowModel {
     nodes: Rc<VecModel<SlintNode>>,
     edges: Rc<VecModel<SlintEdge>>,
@@ -49,7 +60,250 @@ impl Default for WindowModel {
     }
 }
 
+// Number of command log entries shown on the home page
+const COMMAND_LOG_LINES: usize = 5;
+
+pub struct WindowObserver {
+    window: Weak<MainWindow>,
+    config: Arc<Mutex<Config>>,
+    command_log: Vec<SharedString>,
+}
+
+impl WindowObserver {
+    pub fn new(window: Weak<MainWindow>, config: Arc<Mutex<Config>>) -> Self {
+        WindowObserver {
+            window,
+            config,
+            command_log: Vec::new(),
+        }
+    }
+}
+
+impl Observer for WindowObserver {
+    fn state_changed(&mut self, state: &State<StateIndex>) {
+        MainWindow::update_active_node_id(self.window.clone(), state.id());
+    }
+
+    fn frame_processed(&mut self, frame: &RgbImage) {
+        let window = self.window.clone();
+        let frame = frame.clone();
+
+        slint::invoke_from_event_loop(move || {
+            let window = window.unwrap();
+
+            let camera_visible = window.get_active_page() == 0;
+            if camera_visible {
+                let buffer = SharedPixelBuffer::<Rgb8Pixel>::clone_from_slice(
+                    frame.as_raw(),
+                    frame.width(),
+                    frame.height(),
+                );
+                window.set_webcam_image(Image::from_rgb8(buffer));
+            }
+        });
+    }
+
+    fn command_logged(&mut self, log: &CommandLog) {
+        if self.command_log.len() == COMMAND_LOG_LINES {
+            self.command_log.remove(0);
+        }
+        self.command_log.push(log.to_string().into());
+
+        let window = self.window.clone();
+        let command_log = self.command_log.clone();
+
+        slint::invoke_from_event_loop(move || {
+            window
+                .unwrap()
+                .set_command_log(Rc::new(VecModel::from(command_log)).into());
+        });
+    }
+
+    fn sign_captured(&mut self, name: &str, sign: &CoreSign) {
+        let window = self.window.clone();
+        let name = SharedString::from(name);
+        let required = Vec::<bool>::from(sign.required_attributes());
+        let irrelevant = Vec::<bool>::from(sign.irrelevant_attributes());
+        let config = Arc::clone(&self.config);
+
+        slint::invoke_from_event_loop(move || {
+            let window = window.unwrap();
+            let signs = window.get_signs();
+
+            for (i, mut row) in signs.iter().enumerate() {
+                if row.name == name {
+                    row.required_flags = Rc::new(VecModel::from(required)).into();
+                    row.irrelevant_flags = Rc::new(VecModel::from(irrelevant)).into();
+                    signs.set_row_data(i, row);
+                    break;
+                }
+            }
+            window.set_capturing_sign(SharedString::default());
+            window.update_sign_conflicts(&config.lock().unwrap());
+        });
+    }
+
+    fn template_recorded(&mut self, name: &str) {
+        let window = self.window.clone();
+        let name = SharedString::from(name);
+
+        slint::invoke_from_event_loop(move || {
+            let window = window.unwrap();
+            let mut trajectories: Vec<SharedString> = window.get_trajectories().iter().collect();
+
+            if !trajectories.contains(&name) {
+                trajectories.push(name);
+                trajectories.sort();
+            }
+            window.set_trajectories(Rc::new(VecModel::from(trajectories)).into());
+            window.set_recording_trajectory(false);
+        });
+    }
+
+    fn calibration_captured(&mut self, next: Option<CalibrationPose>) {
+        let window = self.window.clone();
+
+        slint::invoke_from_event_loop(move || {
+            let window = window.unwrap();
+
+            window.set_calibrating(false);
+            window.show_calibration_pose(next);
+        });
+    }
+
+    fn calibration_failed(&mut self, error: &CalibrationError) {
+        let window = self.window.clone();
+        let error = error.to_string();
+
+        eprintln!("ERROR: {}", error);
+        slint::invoke_from_event_loop(move || {
+            let window = window.unwrap();
+            let pose = CalibrationPose::iter().next();
+
+            window.set_calibrating(false);
+            window.show_calibration_pose(pose);
+            window.set_calibration_instructions(
+                format!(
+                    "{} {}",
+                    error,
+                    pose.map(|pose| pose.instructions()).unwrap_or_default()
+                )
+                .into(),
+            );
+        });
+    }
+}
+
 impl MainWindow {
+    pub fn init(&self, config: Arc<Mutex<Config>>) {
+        let window_model = Rc::new(WindowModel::default());
+
+        self.set_active_node_id(INITIAL_STATE_INDEX);
+        self.update_features();
+        self.update_signs(config.clone(), window_model.signs());
+        self.update_state_graph(config.clone(), window_model.clone());
+        self.update_settings(config.clone());
+        self.attach_config_callbacks(config, window_model);
+    }
+
+    pub fn update_settings(&self, config: Arc<Mutex<Config>>) {
+        let config = config.lock().unwrap();
+
+        self.set_panic_sign(config.panic_sign.clone().unwrap_or_default().into());
+        let hands: Vec<SharedString> = std::iter::once(ANY_HAND)
+            .chain(Handedness::VARIANTS.iter().copied())
+            .map(SharedString::from)
+            .collect();
+        self.set_hands(Rc::new(VecModel::from(hands)).into());
+        let sign_modes: Vec<SharedString> = SignMode::VARIANTS
+            .iter()
+            .map(|s| SharedString::from(*s))
+            .collect();
+        self.set_sign_modes(Rc::new(VecModel::from(sign_modes)).into());
+        self.set_primary_hand(hand_name(config.primary_hand).into());
+        self.set_swipe_distance(config.swipe.distance.to_string().into());
+        self.set_swipe_speed(config.swipe.speed.to_string().into());
+        self.set_push_distance(config.push.distance.to_string().into());
+        self.update_trajectories(&config);
+    }
+
+    fn update_trajectories(&self, config: &Config) {
+        let trajectories: Vec<SharedString> = config
+            .trajectory_dictionary()
+            .templates()
+            .keys()
+            .map(SharedString::from)
+            .collect();
+
+        self.set_trajectories(Rc::new(VecModel::from(trajectories)).into());
+    }
+
+    fn record_trajectory(&self, name: String, config: Arc<Mutex<Config>>) {
+        let name = name.trim();
+
+        if name.is_empty() {
+            eprintln!("ERROR: Trajectory templates need a name");
+            return;
+        }
+
+        config.lock().unwrap().capture = Some(Capture::Trajectory(name.to_string()));
+        self.set_recording_trajectory(true);
+    }
+
+    // Shows the first pose, then captures the shown one
+    fn calibrate(&self, config: Arc<Mutex<Config>>) {
+        let pose = match CalibrationPose::from_str(&self.get_calibration_pose()) {
+            Ok(pose) => pose,
+            Err(_) => {
+                self.show_calibration_pose(CalibrationPose::iter().next());
+                return;
+            }
+        };
+
+        config.lock().unwrap().capture = Some(Capture::Calibration {
+            pose,
+            frames: CALIBRATION_FRAMES,
+        });
+        self.set_calibrating(true);
+    }
+
+    fn show_calibration_pose(&self, pose: Option<CalibrationPose>) {
+        let (name, instructions) = match pose {
+            Some(pose) => (pose.to_string(), pose.instructions()),
+            None => (String::new(), ""),
+        };
+
+        self.set_calibration_pose(name.into());
+        self.set_calibration_instructions(instructions.into());
+    }
+
+    fn delete_trajectory(
+        &self,
+        name: String,
+        config: Arc<Mutex<Config>>,
+        edges: Rc<VecModel<SlintEdge>>,
+    ) {
+        let mut config = config.lock().unwrap();
+
+        config
+            .trajectory_dictionary_mut()
+            .templates_mut()
+            .remove(&name);
+        for (_, edge) in config
+            .state_graph_mut()
+            .edge_iter_mut()
+            .flat_map(|(_, e)| e.iter_mut())
+        {
+            edge.trigger = edge
+                .trigger
+                .take()
+                .and_then(|trigger| trigger.delete_template(&name));
+        }
+
+        self.update_edges(config.state_graph_mut(), edges);
+        self.update_trajectories(&config);
+    }
+
     pub fn attach_config_callbacks(
         &self,
         config: Arc<Mutex<Config>>,
@@ -97,6 +351,80 @@ impl MainWindow {
             }
         });
 
+        self.on_set_sign_tuning({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+
+            move |sign_name, threshold, smoothness, priority| {
+                window.unwrap().set_sign_tuning(
+                    sign_name.to_string(),
+                    threshold.to_string(),
+                    smoothness.to_string(),
+                    priority.to_string(),
+                    config.clone(),
+                )
+            }
+        });
+
+        self.on_set_sign_hand({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+
+            move |sign_name, hand| {
+                let window = window.unwrap();
+                let mut config = config.lock().unwrap();
+
+                config
+                    .sign_dictionary_mut()
+                    .signs_mut()
+                    .get_mut(sign_name.as_str())
+                    .expect("BUG: Unknown sign.")
+                    .hand = Handedness::from_str(&hand).ok();
+                window.update_sign_conflicts(&config);
+            }
+        });
+
+        self.on_set_sign_mode({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+
+            move |sign_name, mode| {
+                let window = window.unwrap();
+                let mut config = config.lock().unwrap();
+
+                match SignMode::from_str(&mode) {
+                    Ok(mode) => {
+                        let sign = config
+                            .sign_dictionary_mut()
+                            .signs_mut()
+                            .get_mut(sign_name.as_str())
+                            .expect("BUG: Unknown sign.");
+                        sign.mode = mode;
+
+                        if mode == SignMode::Learned && sign.examples.is_empty() {
+                            eprintln!(
+                                "WARNING: {} has no examples yet, capture it to recognize it",
+                                sign_name
+                            );
+                        }
+                    }
+                    Err(_) => eprintln!("ERROR: Invalid sign mode: {}", mode),
+                }
+                window.update_sign_conflicts(&config);
+            }
+        });
+
+        self.on_capture_sign({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+
+            move |sign_name| {
+                window
+                    .unwrap()
+                    .capture_sign(sign_name.to_string(), config.clone())
+            }
+        });
+
         self.on_set_sign_name({
             let window = self.as_weak();
             let config = Arc::clone(&config);
@@ -185,6 +513,91 @@ impl MainWindow {
             }
         });
 
+        self.on_node_tags_updated({
+            let window_weak = self.as_weak();
+            let config_clone = Arc::clone(&config);
+            let window_model = window_model.clone();
+
+            move |node| {
+                window_weak.unwrap().node_tags_updated(
+                    node,
+                    config_clone.clone(),
+                    window_model.nodes.clone(),
+                );
+            }
+        });
+
+        self.on_panic_sign_selected({
+            let config = Arc::clone(&config);
+
+            move |sign| {
+                config.lock().unwrap().panic_sign = Some(sign.to_string());
+            }
+        });
+
+        self.on_primary_hand_selected({
+            let config = Arc::clone(&config);
+
+            move |hand| {
+                config.lock().unwrap().primary_hand = Handedness::from_str(&hand).ok();
+            }
+        });
+
+        self.on_swipe_thresholds_edited({
+            let config = Arc::clone(&config);
+
+            move |distance, speed| match (distance.trim().parse(), speed.trim().parse()) {
+                (Ok(distance), Ok(speed)) => {
+                    let mut config = config.lock().unwrap();
+
+                    config.swipe.distance = distance;
+                    config.swipe.speed = speed;
+                }
+                _ => eprintln!("ERROR: Invalid swipe thresholds: {}, {}", distance, speed),
+            }
+        });
+
+        self.on_push_distance_edited({
+            let config = Arc::clone(&config);
+
+            move |distance| match distance.trim().parse() {
+                Ok(distance) => config.lock().unwrap().push.distance = distance,
+                Err(_) => eprintln!("ERROR: Invalid push distance: {}", distance),
+            }
+        });
+
+        self.on_record_trajectory({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+
+            move |name| {
+                window
+                    .unwrap()
+                    .record_trajectory(name.to_string(), config.clone())
+            }
+        });
+
+        self.on_calibrate({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+
+            move || window.unwrap().calibrate(config.clone())
+        });
+
+        self.on_delete_trajectory({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+            let window_model = window_model.clone();
+
+            move |name| {
+                window.unwrap().delete_trajectory(
+                    name.to_string(),
+                    config.clone(),
+                    window_model.edges.clone(),
+                )
+            }
+        });
+
         self.on_add_edge({
             let window = self.as_weak();
             let config = Arc::clone(&config);
@@ -212,15 +625,30 @@ impl MainWindow {
             }
         });
 
+        self.on_edge_options_updated({
+            let window = self.as_weak();
+            let config = Arc::clone(&config);
+            let window_model = window_model.clone();
+
+            move |edge| {
+                window.unwrap().edge_options_updated(
+                    edge,
+                    config.clone(),
+                    window_model.edges.clone(),
+                )
+            }
+        });
+
         self.on_set_edge_trigger({
             let window = self.as_weak();
             let config = Arc::clone(&config);
             let window_model = window_model;
 
-            move |edge, trigger| {
+            move |edge, trigger_type, value| {
                 window.unwrap().set_edge_trigger(
                     edge,
-                    trigger.to_string(),
+                    trigger_type.to_string(),
+                    value.to_string(),
                     config.clone(),
                     window_model.edges.clone(),
                 )
@@ -234,22 +662,13 @@ impl MainWindow {
         let sign_name = config.sign_dictionary_mut().next_valid_name();
         let sign = CoreSign::default();
 
-        signs.push(SlintSign {
-            name: sign_name.clone().into(),
-            required_flags: Rc::new(VecModel::from(Vec::<bool>::from(
-                sign.required_attributes(),
-            )))
-            .into(),
-            irrelevant_flags: Rc::new(VecModel::from(Vec::<bool>::from(
-                sign.irrelevant_attributes(),
-            )))
-            .into(),
-        });
+        signs.push(slint_sign(&sign_name, &sign));
 
         config
             .sign_dictionary_mut()
             .signs_mut()
             .insert(sign_name, sign);
+        self.update_sign_conflicts(&config);
         std::mem::drop(config);
 
         self.refresh_triggers(config_clone);
@@ -276,7 +695,11 @@ impl MainWindow {
             }
         }
 
-        self.broadcast_trigger_update(&mut config.lock().unwrap(), &name, None, edges);
+        let mut config_guard = config.lock().unwrap();
+        self.broadcast_trigger_update(&mut config_guard, &name, None, edges);
+        self.update_sign_conflicts(&config_guard);
+        std::mem::drop(config_guard);
+
         self.refresh_triggers(config);
     }
 
@@ -288,14 +711,60 @@ impl MainWindow {
         required: bool,
         config: Arc<Mutex<Config>>,
     ) {
+        let mut config = config.lock().unwrap();
+
         config
-            .lock()
-            .unwrap()
             .sign_dictionary_mut()
             .signs_mut()
             .get_mut(&name)
             .expect("BUG: Unknown sign.")
             .set_feature(feature_index, irrelevant, required);
+        self.update_sign_conflicts(&config);
+    }
+
+    fn set_sign_tuning(
+        &self,
+        name: String,
+        threshold: String,
+        smoothness: String,
+        priority: String,
+        config: Arc<Mutex<Config>>,
+    ) {
+        let (threshold, smoothness, priority) = match (
+            parse_ratio(&threshold),
+            parse_ratio(&smoothness),
+            parse_priority(&priority),
+        ) {
+            (Some(threshold), Some(smoothness), Some(priority)) => {
+                (threshold, smoothness, priority)
+            }
+            _ => {
+                eprintln!(
+                    "ERROR: Invalid threshold, smoothing or priority for {}: {}, {}, {}",
+                    name, threshold, smoothness, priority
+                );
+                return;
+            }
+        };
+
+        let mut config = config.lock().unwrap();
+        let sign = config
+            .sign_dictionary_mut()
+            .signs_mut()
+            .get_mut(&name)
+            .expect("BUG: Unknown sign.");
+
+        sign.threshold = threshold;
+        sign.smoothness = smoothness;
+        sign.priority = priority;
+        self.update_sign_conflicts(&config);
+    }
+
+    fn capture_sign(&self, name: String, config: Arc<Mutex<Config>>) {
+        config.lock().unwrap().capture = Some(Capture::Sign {
+            name,
+            frames: SIGN_CAPTURE_FRAMES,
+        });
     }
 
     fn set_sign_name(
@@ -313,6 +782,7 @@ impl MainWindow {
         signs.insert(new_name.clone(), sign);
 
         self.broadcast_trigger_update(&mut config, &old_name, Some(&new_name), edges);
+        self.update_sign_conflicts(&config);
 
         std::mem::drop(config);
         self.refresh_triggers(config_clone);
@@ -321,26 +791,25 @@ impl MainWindow {
     fn broadcast_trigger_update(
         &self,
         config: &mut Config,
-        old_trigger: &String,
+        old_trigger: &str,
         new_trigger: Option<&String>,
         edges: Rc<VecModel<SlintEdge>>,
     ) {
-        for mut edge in edges.iter() {
-            if edge.title == old_trigger {
-                edge.title = new_trigger.unwrap_or(&String::default()).into();
-            }
-        }
-
-        for mut edge in config
+        for (_, edge) in config
             .state_graph_mut()
             .edge_iter_mut()
-            .flat_map(|(_, e)| e.values_mut())
+            .flat_map(|(_, e)| e.iter_mut())
         {
-            if let Some(ref mut edge_trigger) = edge.trigger {
-                if edge_trigger == old_trigger {
-                    edge.trigger = new_trigger.cloned();
-                }
-            }
+            edge.trigger = edge.trigger.take().and_then(|trigger| {
+                trigger.rename_sign(old_trigger, new_trigger.map(|s| s.as_str()))
+            });
+        }
+
+        self.update_edges(config.state_graph_mut(), edges);
+
+        if config.panic_sign.as_deref() == Some(old_trigger) {
+            config.panic_sign = new_trigger.cloned();
+            self.set_panic_sign(config.panic_sign.clone().unwrap_or_default().into());
         }
     }
 
@@ -357,30 +826,60 @@ impl MainWindow {
                 .sign_dictionary()
                 .signs()
                 .iter()
-                .map(|(name, sign)| SlintSign {
-                    name: name.into(),
-                    required_flags: Rc::new(VecModel::from(Vec::<bool>::from(
-                        sign.required_attributes(),
-                    )))
-                    .into(),
-                    irrelevant_flags: Rc::new(VecModel::from(Vec::<bool>::from(
-                        sign.irrelevant_attributes(),
-                    )))
-                    .into(),
-                })
+                .map(|(name, sign)| slint_sign(name, sign))
                 .collect::<Vec<SlintSign>>(),
         );
 
         self.set_signs(signs.into());
+        self.update_sign_conflicts(&config.lock().unwrap());
         self.refresh_triggers(config);
     }
 
+    // Also warns about the learned signs that can't be recognized yet
+    pub fn update_sign_conflicts(&self, config: &Config) {
+        let dictionary = config.sign_dictionary();
+        let conflicts: Vec<SharedString> = dictionary
+            .conflicts()
+            .iter()
+            .map(|conflict| conflict.to_string())
+            .chain(
+                dictionary
+                    .untrained()
+                    .iter()
+                    .map(|name| format!("{} is learned but has no examples yet, capture it", name)),
+            )
+            .map(SharedString::from)
+            .collect();
+
+        self.set_sign_conflicts(Rc::new(VecModel::from(conflicts)).into());
+    }
+
     pub fn refresh_triggers(&self, config: Arc<Mutex<Config>>) {
         let config = config.lock().unwrap();
         let signs = config.sign_dictionary().signs();
 
         let triggers: Vec<SharedString> = signs.keys().map(SharedString::from).collect();
+        let trigger_types: Vec<SharedString> = TriggerDiscriminants::VARIANTS
+            .iter()
+            .map(|s| SharedString::from(*s))
+            .collect();
+        let hold_types: Vec<SharedString> = std::iter::once(NO_HOLD)
+            .chain(HoldDiscriminants::VARIANTS.iter().copied())
+            .map(SharedString::from)
+            .collect();
         self.set_triggers(Rc::new(VecModel::from(triggers)).into());
+        let directions: Vec<SharedString> = Direction::VARIANTS
+            .iter()
+            .map(|s| SharedString::from(*s))
+            .collect();
+        self.set_trigger_types(Rc::new(VecModel::from(trigger_types)).into());
+        self.set_directions(Rc::new(VecModel::from(directions)).into());
+        let depth_motions: Vec<SharedString> = DepthMotion::VARIANTS
+            .iter()
+            .map(|s| SharedString::from(*s))
+            .collect();
+        self.set_depth_motions(Rc::new(VecModel::from(depth_motions)).into());
+        self.set_hold_types(Rc::new(VecModel::from(hold_types)).into());
     }
 
     pub fn update_state_graph(&self, config: Arc<Mutex<Config>>, window_model: Rc<WindowModel>) {
@@ -397,6 +896,10 @@ impl MainWindow {
             .iter()
             .map(|s| SharedString::from(*s))
             .collect();
+        let keys: Vec<SharedString> = Key::VARIANTS
+            .iter()
+            .map(|s| SharedString::from(*s))
+            .collect();
         let key_events: Vec<SharedString> = KeyEvent::VARIANTS
             .iter()
             .map(|s| SharedString::from(*s))
@@ -435,10 +938,7 @@ impl MainWindow {
                                 .get(&core_edge.next())
                                 .expect("ERROR: Invalid node to_id"),
                         );
-                        let title =
-                            SharedString::from(core_edge.trigger().clone().unwrap_or_default());
-
-                        Edge { from, to, title }
+                        slint_edge(from, to, core_edge)
                     })
                 })
                 .collect::<Vec<SlintEdge>>(),
@@ -447,6 +947,7 @@ impl MainWindow {
         self.set_node_types(Rc::new(VecModel::from(node_types)).into());
         self.set_command_types(Rc::new(VecModel::from(command_types)).into());
         self.set_mouse_buttons(Rc::new(VecModel::from(mouse_buttons)).into());
+        self.set_keys(Rc::new(VecModel::from(keys)).into());
         self.set_key_events(Rc::new(VecModel::from(key_events)).into());
         self.set_axes(Rc::new(VecModel::from(axes)).into());
         self.set_nodes(window_model.nodes.clone().into());
@@ -542,10 +1043,14 @@ impl MainWindow {
             .get_node_mut(&node.id)
             .expect("ERROR: Invalid Node ID.");
 
-        core_node.set_command(
-            StateEvent::from_str(command.title.as_str()).unwrap(),
-            (&command).into(),
-        );
+        // Invalid input keeps the previous command
+        match CoreCommand::try_from(&command) {
+            Ok(core_command) => core_node.set_command(
+                StateEvent::from_str(command.title.as_str()).unwrap(),
+                core_command,
+            ),
+            Err(err) => eprintln!("ERROR: {}", err),
+        }
     }
 
     fn node_type_updated(
@@ -560,10 +1065,43 @@ impl MainWindow {
             .state_graph_mut()
             .get_node_mut(&updated_node.id)
             .expect("Consistency Error: Invalid node ID");
+        let r#type =
+            StateType::from_str(updated_node.r#type.as_str()).expect("Error: Invalid state type");
+
+        // Wildcards are never entered, the machine starts in the initial state and may be in the
+        // active one
+        if r#type == StateType::Wildcard
+            && (updated_node.id == INITIAL_STATE_INDEX
+                || updated_node.id == self.get_active_node_id())
+        {
+            eprintln!("ERROR: The initial and the active states can not be wildcards");
+        } else {
+            state.set_type(r#type);
+        }
 
-        state.set_type(
-            StateType::from_str(updated_node.r#type.as_str()).expect("Error: Invalid state type"),
-        );
+        self.force_node_update(SlintNode::from(state as &State<StateIndex>), nodes);
+    }
+
+    fn node_tags_updated(
+        &self,
+        updated_node: SlintNode,
+        config: Arc<Mutex<Config>>,
+        nodes: Rc<VecModel<SlintNode>>,
+    ) {
+        let mut config = config.lock().unwrap();
+
+        let state = config
+            .state_graph_mut()
+            .get_node_mut(&updated_node.id)
+            .expect("Consistency Error: Invalid node ID");
+
+        state.tags = updated_node
+            .tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect();
 
         self.force_node_update(SlintNode::from(state as &State<StateIndex>), nodes);
     }
@@ -606,12 +1144,11 @@ impl MainWindow {
         let mut config = config.lock().unwrap();
         let graph = config.state_graph_mut();
 
-        if graph.add_edge(&from_node.id, ConditionalEdge::new(to_node.id, None)) {
-            edges.push(Edge {
-                title: SharedString::default(),
-                from: from_node,
-                to: to_node,
-            });
+        let edge = ConditionalEdge::new(to_node.id, None);
+        let slint_edge = slint_edge(from_node.clone(), to_node, &edge);
+
+        if graph.add_edge(&from_node.id, edge) {
+            edges.push(slint_edge);
         }
     }
 
@@ -637,23 +1174,100 @@ impl MainWindow {
     pub fn set_edge_trigger(
         &self,
         updated_edge: SlintEdge,
-        trigger: String,
+        trigger_type: String,
+        value: String,
         config: Arc<Mutex<Config>>,
         edges: Rc<VecModel<SlintEdge>>,
     ) {
+        let trigger = match trigger_from_parts(&trigger_type, value.trim()) {
+            Some(trigger) => trigger,
+            None => {
+                eprintln!("ERROR: Invalid {} trigger: {}", trigger_type, value);
+                return;
+            }
+        };
+
         let mut config = config.lock().unwrap();
-        let graph = config.state_graph_mut();
+        let signs = config.sign_dictionary().signs();
+        if let Some(unknown) = trigger
+            .signs()
+            .iter()
+            .find(|sign| !signs.contains_key(**sign))
+        {
+            eprintln!("ERROR: Unknown sign in the trigger: {}", unknown);
+            return;
+        }
+        if let Trigger::Trajectory(ref template) = trigger {
+            if !config
+                .trajectory_dictionary()
+                .templates()
+                .contains_key(template)
+            {
+                eprintln!("ERROR: Unknown trajectory in the trigger: {}", template);
+                return;
+            }
+        }
 
-        graph
+        let edge = config
+            .state_graph_mut()
             .get_edge_mut(&updated_edge.from.id, &updated_edge.to.id)
-            .expect("Consistency Error: Invalid edge")
-            .trigger = Some(trigger.clone());
+            .expect("Consistency Error: Invalid edge");
+        edge.trigger = Some(trigger);
+
+        self.replace_edge(slint_edge(updated_edge.from, updated_edge.to, edge), edges);
+    }
 
-        for (i, mut edge) in edges.iter().enumerate() {
+    pub fn edge_options_updated(
+        &self,
+        updated_edge: SlintEdge,
+        config: Arc<Mutex<Config>>,
+        edges: Rc<VecModel<SlintEdge>>,
+    ) {
+        let hold = match hold_from_parts(&updated_edge.hold_type, updated_edge.hold_value.trim()) {
+            Some(hold) => hold,
+            None => {
+                eprintln!(
+                    "ERROR: Invalid {} hold: {}",
+                    updated_edge.hold_type, updated_edge.hold_value
+                );
+                return;
+            }
+        };
+        let threshold = match parse_ratio(&updated_edge.threshold) {
+            Some(threshold) => threshold,
+            None => {
+                eprintln!("ERROR: Invalid threshold: {}", updated_edge.threshold);
+                return;
+            }
+        };
+        let cooldown = match updated_edge.cooldown.trim() {
+            "" => None,
+            cooldown => match cooldown.parse() {
+                Ok(cooldown) => Some(cooldown),
+                Err(_) => {
+                    eprintln!("ERROR: Invalid cooldown: {}", cooldown);
+                    return;
+                }
+            },
+        };
+
+        let mut config = config.lock().unwrap();
+        let edge = config
+            .state_graph_mut()
+            .get_edge_mut(&updated_edge.from.id, &updated_edge.to.id)
+            .expect("Consistency Error: Invalid edge");
+        edge.hold = hold;
+        edge.cooldown = cooldown;
+        edge.threshold = threshold;
+
+        self.replace_edge(slint_edge(updated_edge.from, updated_edge.to, edge), edges);
+    }
+
+    fn replace_edge(&self, updated_edge: SlintEdge, edges: Rc<VecModel<SlintEdge>>) {
+        for (i, edge) in edges.iter().enumerate() {
             if edge.from.id == updated_edge.from.id && edge.to.id == updated_edge.to.id {
-                edge.title = trigger.clone().into();
                 edges.remove(i);
-                edges.push(edge);
+                edges.push(updated_edge);
                 break;
             }
         }
@@ -680,10 +1294,7 @@ impl MainWindow {
                                     .get(&core_edge.next())
                                     .expect("ERROR: Invalid node to_id"),
                             );
-                            let title =
-                                SharedString::from(core_edge.trigger().clone().unwrap_or_default());
-
-                            SlintEdge { from, to, title }
+                            slint_edge(from, to, core_edge)
                         }
                     })
                 })
@@ -692,6 +1303,188 @@ impl MainWindow {
     }
 }
 
+fn slint_edge(
+    from: SlintNode,
+    to: SlintNode,
+    edge: &ConditionalEdge<StateIndex, Option<Trigger>>,
+) -> SlintEdge {
+    let trigger = edge.trigger().as_ref();
+    let (trigger_type, trigger_value) = match trigger {
+        Some(Trigger::Sign(sign)) => (TriggerDiscriminants::Sign, sign.clone()),
+        Some(Trigger::AnyOf(signs)) => (TriggerDiscriminants::AnyOf, signs.join(", ")),
+        Some(Trigger::Sequence { signs, window }) => (
+            TriggerDiscriminants::Sequence,
+            format!("{} within {}", signs.join(", "), window),
+        ),
+        Some(trigger @ Trigger::TwoHands { .. }) => {
+            (TriggerDiscriminants::TwoHands, trigger.to_string())
+        }
+        Some(Trigger::Swipe(direction)) => (TriggerDiscriminants::Swipe, direction.to_string()),
+        Some(Trigger::Depth(motion)) => (TriggerDiscriminants::Depth, motion.to_string()),
+        Some(Trigger::Trajectory(template)) => (TriggerDiscriminants::Trajectory, template.clone()),
+        Some(Trigger::Timeout(ms)) => (TriggerDiscriminants::Timeout, ms.to_string()),
+        Some(Trigger::HandLost(ms)) => (TriggerDiscriminants::HandLost, ms.to_string()),
+        None => (TriggerDiscriminants::Sign, String::new()),
+    };
+    let (hold_type, hold_value) = match edge.hold {
+        Some(Hold::Duration(ms)) => (HoldDiscriminants::Duration.to_string(), ms.to_string()),
+        Some(Hold::Frames(frames)) => (HoldDiscriminants::Frames.to_string(), frames.to_string()),
+        None => (NO_HOLD.to_string(), String::new()),
+    };
+
+    SlintEdge {
+        title: trigger.map(Trigger::to_string).unwrap_or_default().into(),
+        trigger_type: trigger_type.to_string().into(),
+        trigger_value: trigger_value.into(),
+        hold_type: hold_type.into(),
+        hold_value: hold_value.into(),
+        cooldown: edge
+            .cooldown
+            .map(|ms| ms.to_string())
+            .unwrap_or_default()
+            .into(),
+        threshold: edge
+            .threshold
+            .map(|threshold| threshold.to_string())
+            .unwrap_or_default()
+            .into(),
+        from,
+        to,
+    }
+}
+
+fn slint_sign(name: &str, sign: &CoreSign) -> SlintSign {
+    SlintSign {
+        name: name.into(),
+        required_flags: Rc::new(VecModel::from(Vec::<bool>::from(
+            sign.required_attributes(),
+        )))
+        .into(),
+        irrelevant_flags: Rc::new(VecModel::from(Vec::<bool>::from(
+            sign.irrelevant_attributes(),
+        )))
+        .into(),
+        threshold: sign
+            .threshold
+            .map(|threshold| threshold.to_string())
+            .unwrap_or_default()
+            .into(),
+        smoothness: sign
+            .smoothness
+            .map(|smoothness| smoothness.to_string())
+            .unwrap_or_default()
+            .into(),
+        priority: sign
+            .priority
+            .map(|priority| priority.to_string())
+            .unwrap_or_default()
+            .into(),
+        hand: hand_name(sign.hand).into(),
+        mode: sign.mode.to_string().into(),
+    }
+}
+
+fn hand_name(hand: Option<Handedness>) -> String {
+    match hand {
+        Some(hand) => hand.to_string(),
+        None => String::from(ANY_HAND),
+    }
+}
+
+// None if invalid, Some(None) to fall back to the default
+fn parse_ratio(value: &str) -> Option<Option<f32>> {
+    let value = value.trim();
+
+    if value.is_empty() || value.eq_ignore_ascii_case("default") {
+        return Some(None);
+    }
+
+    match value.parse::<f32>() {
+        Ok(ratio) if (0f32..=1f32).contains(&ratio) => Some(Some(ratio)),
+        _ => None,
+    }
+}
+
+// None if invalid, Some(None) to fall back to the default
+fn parse_priority(value: &str) -> Option<Option<i32>> {
+    let value = value.trim();
+
+    if value.is_empty() || value.eq_ignore_ascii_case("default") {
+        return Some(None);
+    }
+
+    value.parse().ok().map(Some)
+}
+
+fn trigger_from_parts(trigger_type: &str, value: &str) -> Option<Trigger> {
+    match TriggerDiscriminants::from_str(trigger_type).ok()? {
+        TriggerDiscriminants::Sign => Some(Trigger::Sign(value.to_string())),
+        // Comma separated signs, e.g. "FIST, PALM" or "FIST, PALM within 800"
+        TriggerDiscriminants::AnyOf => Some(Trigger::AnyOf(sign_list(value)?)),
+        TriggerDiscriminants::Sequence => {
+            let (signs, window) = value.rsplit_once("within")?;
+
+            Some(Trigger::Sequence {
+                signs: sign_list(signs)?,
+                window: window.trim().parse().ok()?,
+            })
+        }
+        // The left and the right hand sign, e.g. "FIST + PALM" or "FIST + PALM, Apart"
+        TriggerDiscriminants::TwoHands => {
+            let (signs, distance) = match value.split_once(',') {
+                Some((signs, distance)) => {
+                    (signs, Some(HandDistance::from_str(distance.trim()).ok()?))
+                }
+                None => (value, None),
+            };
+            let (left, right) = signs.split_once('+')?;
+            let (left, right) = (left.trim(), right.trim());
+
+            if left.is_empty() || right.is_empty() {
+                return None;
+            }
+
+            Some(Trigger::TwoHands {
+                left: left.to_string(),
+                right: right.to_string(),
+                distance,
+            })
+        }
+        TriggerDiscriminants::Swipe => Direction::from_str(value).ok().map(Trigger::Swipe),
+        TriggerDiscriminants::Depth => DepthMotion::from_str(value).ok().map(Trigger::Depth),
+        TriggerDiscriminants::Trajectory => Some(Trigger::Trajectory(value.to_string())),
+        TriggerDiscriminants::Timeout => value.parse().ok().map(Trigger::Timeout),
+        TriggerDiscriminants::HandLost => value.parse().ok().map(Trigger::HandLost),
+    }
+}
+
+fn sign_list(value: &str) -> Option<Vec<String>> {
+    let signs: Vec<String> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|sign| !sign.is_empty())
+        .map(String::from)
+        .collect();
+
+    if signs.is_empty() {
+        None
+    } else {
+        Some(signs)
+    }
+}
+
+// None if invalid, Some(None) for the edges firing right away
+fn hold_from_parts(hold_type: &str, value: &str) -> Option<Option<Hold>> {
+    if hold_type == NO_HOLD {
+        return Some(None);
+    }
+
+    match HoldDiscriminants::from_str(hold_type).ok()? {
+        HoldDiscriminants::Duration => value.parse().ok().map(|ms| Some(Hold::Duration(ms))),
+        HoldDiscriminants::Frames => value.parse().ok().map(|frames| Some(Hold::Frames(frames))),
+    }
+}
+
 impl From<&State<StateIndex>> for SlintNode {
     fn from(state: &State<StateIndex>) -> Self {
         let commands: Vec<SlintCommand> = state
@@ -707,6 +1500,7 @@ impl From<&State<StateIndex>> for SlintNode {
             y: state.y,
 
             r#type: state.r#type().to_string().into(),
+            tags: state.tags.join(", ").into(),
             commands: Rc::new(VecModel::from(commands)).into(),
         }
     }
@@ -743,17 +1537,26 @@ impl From<(&StateEvent, &Option<CoreCommand>)> for SlintCommand {
 impl From<(&StateEvent, &CoreCommand)> for SlintCommand {
     fn from(event: (&StateEvent, &CoreCommand)) -> Self {
         let command = event.1;
-        let (mouse_button, key_event) = if let CoreCommand::Mouse(mouse_button, key_event) = command
-        {
-            (
-                mouse_button.to_string().into(),
-                key_event.to_string().into(),
-            )
+        let exec_command = if let CoreCommand::Execute(exec_command) = command {
+            exec_command.clone()
         } else {
-            (
-                MouseButton::Left.to_string().into(),
-                KeyEvent::Click.to_string().into(),
-            )
+            ExecCommand::default()
+        };
+        let mouse_button = if let CoreCommand::Mouse(mouse_button, _) = command {
+            mouse_button.to_string().into()
+        } else {
+            MouseButton::Left.to_string().into()
+        };
+        let key = if let CoreCommand::Key(key, _) = command {
+            key.to_string().into()
+        } else {
+            Key::VARIANTS[0].into()
+        };
+        let key_event = match command {
+            CoreCommand::Mouse(_, key_event) | CoreCommand::Key(_, key_event) => {
+                key_event.to_string().into()
+            }
+            _ => KeyEvent::Click.to_string().into(),
         };
 
         let (scroll_custom_command_enabled, scroll_custom_command, scroll_axis, scroll_factor) =
@@ -775,13 +1578,40 @@ impl From<(&StateEvent, &CoreCommand)> for SlintCommand {
         Self {
             title: event.0.to_string().into(),
             r#type: command.to_string().into(),
-            exec_command: if let CoreCommand::Execute(exec_command) = command {
-                exec_command.into()
+            exec_command: exec_command.command.into(),
+            exec_shell: exec_command.shell,
+            exec_timeout: exec_command
+                .timeout
+                .map(|timeout| timeout.to_string())
+                .unwrap_or_default()
+                .into(),
+            exec_working_dir: exec_command.working_dir.unwrap_or_default().into(),
+            exec_env: shell_words::join(
+                exec_command
+                    .env
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value)),
+            )
+            .into(),
+            mouse_button,
+            key,
+            key_event,
+            // Line edits are single line, newlines are shown escaped
+            text: if let CoreCommand::Type(text) = command {
+                escape_text(text).into()
+            } else {
+                "".into()
+            },
+            steps: if let CoreCommand::Sequence(steps) = command {
+                steps_to_string(steps).into()
+            } else {
+                "".into()
+            },
+            shortcut: if let CoreCommand::Shortcut(shortcut) = command {
+                shortcut.to_string().into()
             } else {
                 "".into()
             },
-            mouse_button,
-            key_event,
             scroll_custom_command_enabled,
             scroll_custom_command,
             scroll_axis,
@@ -790,18 +1620,41 @@ impl From<(&StateEvent, &CoreCommand)> for SlintCommand {
     }
 }
 
-impl From<&SlintCommand> for CoreCommand {
-    fn from(command: &SlintCommand) -> Self {
+impl TryFrom<&SlintCommand> for CoreCommand {
+    type Error = String;
+
+    fn try_from(command: &SlintCommand) -> Result<Self, Self::Error> {
         let discriminant = CommandDiscriminants::from_str(command.r#type.as_str())
             .expect("ERROR: Invalid command type.");
 
-        match discriminant {
+        Ok(match discriminant {
             CommandDiscriminants::Disabled => Self::Disabled,
-            CommandDiscriminants::Execute => Self::Execute(command.exec_command.to_string()),
+            CommandDiscriminants::Execute => Self::Execute(ExecCommand {
+                command: command.exec_command.to_string(),
+                shell: command.exec_shell,
+                working_dir: Some(command.exec_working_dir.to_string())
+                    .filter(|dir| !dir.is_empty()),
+                env: shell_words::split(command.exec_env.as_str())
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|var| var.split_once('='))
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                timeout: command.exec_timeout.trim().parse().ok(),
+            }),
             CommandDiscriminants::Mouse => Self::Mouse(
                 MouseButton::from_str(command.mouse_button.as_str()).unwrap(),
                 KeyEvent::from_str(command.key_event.as_str()).unwrap(),
             ),
+            CommandDiscriminants::Key => Self::Key(
+                Key::from_str(command.key.as_str()).unwrap(),
+                KeyEvent::from_str(command.key_event.as_str()).unwrap(),
+            ),
+            CommandDiscriminants::Shortcut => Self::Shortcut(
+                Shortcut::from_str(command.shortcut.as_str())
+                    .map_err(|_| format!("Invalid shortcut: {}", command.shortcut))?,
+            ),
+            CommandDiscriminants::Type => Self::Type(unescape_text(&command.text)),
             CommandDiscriminants::Scroll => Self::Scroll(ScrollCommand {
                 custom_command: if command.scroll_custom_command_enabled {
                     Some(command.scroll_custom_command.clone().into())
@@ -811,6 +1664,10 @@ impl From<&SlintCommand> for CoreCommand {
                 factor: command.scroll_factor,
                 axis: Axis::from_str(command.scroll_axis.as_str()).unwrap(),
             }),
-        }
+            CommandDiscriminants::Sequence => Self::Sequence(
+                steps_from_str(command.steps.as_str())
+                    .map_err(|_| format!("Invalid sequence: {}", command.steps))?,
+            ),
+        })
     }
 }